@@ -0,0 +1,103 @@
+//! Measures how fast channel updates reach thousands of subscribers while
+//! some of them never read, run with `cargo bench --bench fanout`.
+//!
+//! `FANOUT_SUBSCRIBERS` and `FANOUT_UPDATES` change the number of subscribers
+//! and how many updates each active one has to receive.
+
+#[path = "../tests/support/mod.rs"]
+mod support;
+
+use std::time::{Duration, Instant};
+
+use dev_null_backend::ws::{
+    bedrock::BedrockJobConfig,
+    management::{Channel, Command, CommandResponse, Manager, ManagerConfig},
+    minecraft::MinecraftJobConfig,
+};
+use support::fake_server::{FakeServer, Script};
+use tokio::{sync::mpsc, task::JoinSet};
+
+/// One in this many subscribers never reads its updates.
+const STALLED_EVERY: usize = 100;
+
+fn env_or(name: &str, default: usize) -> usize {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+#[tokio::main]
+async fn main() {
+    let subscribers = env_or("FANOUT_SUBSCRIBERS", 5_000);
+    let updates = env_or("FANOUT_UPDATES", 200);
+
+    let status = include_str!("../tests/fixtures/vanilla_1_20_1.json");
+    let other_status = status.replace("jeb_", "Dinnerbone");
+    let server = FakeServer::start(Script::new(status)).await;
+    let manager = Manager::with_config(ManagerConfig {
+        minecraft: MinecraftJobConfig {
+            server_addr: Some(server.addr()),
+            ping_interval: None,
+            status_interval: Some(Duration::from_millis(5)),
+            ..Default::default()
+        },
+        bedrock: BedrockJobConfig {
+            status_interval: None,
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+
+    let started = Instant::now();
+    let mut active = JoinSet::new();
+    let mut stalled = vec![];
+    for subscriber in 0..subscribers {
+        let (tx, mut rx) = mpsc::channel(16);
+        let Some(CommandResponse::Registered(id)) =
+            manager.send_command(Command::Register(tx)).await
+        else {
+            panic!("subscriber should be registered");
+        };
+        manager
+            .send_command(Command::Subscribe(id, Channel::Minecraft))
+            .await;
+
+        if subscriber % STALLED_EVERY == 0 {
+            stalled.push(rx);
+        } else {
+            active.spawn(async move {
+                for _ in 0..updates {
+                    rx.recv().await.expect("subscription should stay open");
+                }
+            });
+        }
+    }
+    println!(
+        "subscribed {subscribers} subscribers ({} stalled) in {:?}",
+        stalled.len(),
+        started.elapsed()
+    );
+
+    // every change of the player list makes a player leave and one join
+    let started = Instant::now();
+    let changes = tokio::spawn(async move {
+        for change in 0.. {
+            server.set_status(if change % 2 == 0 {
+                &other_status
+            } else {
+                status
+            });
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    });
+    while active.join_next().await.is_some() {}
+    let elapsed = started.elapsed();
+    changes.abort();
+
+    let delivered = (subscribers - stalled.len()) * updates;
+    println!(
+        "delivered {updates} updates to every active subscriber in {elapsed:?}, {:.0} messages/s",
+        delivered as f64 / elapsed.as_secs_f64()
+    );
+}
@@ -0,0 +1,68 @@
+//! Records the status response of a live server as a test fixture, exactly as
+//! the server sent it. The mods it decodes to go to stderr, check them against
+//! the server's `mods` folder before committing the capture.
+//!
+//! ```sh
+//! cargo run --example capture_status -- play.example.net 25565 > tests/fixtures/forge_1_20_1.json
+//! ```
+
+use std::{
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+use dev_null_backend::{
+    error::Error,
+    report::mc::{
+        bytes::Bytes,
+        packet::{
+            handshake::{Handshake, NextState, AUTO_PROTOCOL_VERSION},
+            status::{StatusRequest, StatusResponse},
+            Packet, SendPacket,
+        },
+    },
+};
+
+fn main() -> Result<(), Error> {
+    let mut args = std::env::args().skip(1);
+    let (Some(host), port) = (args.next(), args.next()) else {
+        return Err(Error::Generic(
+            "usage: capture_status <host> [port]".to_owned(),
+        ));
+    };
+    let port = port.map_or(Ok(25565), |port| port.parse())?;
+
+    let addr = (host.as_str(), port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| Error::Generic(format!("`{host}` has no address")))?;
+    let mut stream = TcpStream::connect_timeout(&addr, Duration::from_secs(5))?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+
+    Handshake {
+        protocol_version: AUTO_PROTOCOL_VERSION,
+        server_address: host,
+        server_port: port,
+        next_state: NextState::Status,
+    }
+    .send_packet(&mut stream)?;
+    StatusRequest.send_packet(&mut stream)?;
+
+    let mut packet = Packet::recv(&mut stream)?;
+    let json = packet.data.get_string()?;
+    println!("{json}");
+
+    let mut data: Bytes = vec![].into();
+    data.put_string(&json);
+    let status = StatusResponse::try_from(Packet { id: 0x00, data })?;
+    eprintln!("server type: {:?}", status.server_type);
+    for forge_mod in status
+        .forge_data
+        .iter()
+        .flat_map(|forge_data| &forge_data.d.mods)
+    {
+        let version = forge_mod.version.as_deref().unwrap_or("IGNORESERVERONLY");
+        eprintln!("{} {version}", forge_mod.id);
+    }
+    Ok(())
+}
@@ -0,0 +1,8 @@
+#![no_main]
+
+use dev_null_backend::report::mc::packet::status::decode_forge_data_d;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|d: String| {
+    let _ = decode_forge_data_d(d);
+});
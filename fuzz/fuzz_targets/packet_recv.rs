@@ -0,0 +1,10 @@
+#![no_main]
+
+use dev_null_backend::report::mc::packet::{status::StatusResponse, Packet};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(packet) = Packet::recv(&mut &data[..]) {
+        let _ = StatusResponse::try_from(packet);
+    }
+});
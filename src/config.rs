@@ -44,20 +44,20 @@ impl From<Config> for SharedConfig {
 pub async fn watch_config(config: SharedConfig) -> ! {
     match load_config() {
         Ok(new_config) => *config.lock().await = new_config,
-        Err(err) => println!(
-            "Error loading configuration: {err:?}\nUsing default: {}",
+        Err(err) => tracing::warn!(
+            "error loading configuration: {err:?}, using default: {}",
             Config::default()
         ),
     }
 
     loop {
-        println!("Starting config.json watch.");
+        tracing::debug!("starting config.json watch");
         if tokio::spawn(watch_config_file(config.clone()))
             .await
             .err()
             .is_some()
         {
-            println!("Watch config error. Restarting in 10 seconds..");
+            tracing::error!("watch config error, restarting in 10 seconds..");
             tokio::time::sleep(Duration::from_secs(10)).await;
         }
     }
@@ -67,7 +67,7 @@ async fn watch_config_file(config: SharedConfig) -> ! {
     let (tx, mut rx) = tokio::sync::mpsc::channel(10);
     let mut config_watcher = notify::recommended_watcher(move |res| {
         if let Err(send_err) = tx.blocking_send(res) {
-            println!("failed to send the event: {send_err:?}");
+            tracing::error!("failed to send the event: {send_err:?}");
         }
     })
     .expect("config watcher created");
@@ -86,14 +86,14 @@ async fn watch_config_file(config: SharedConfig) -> ! {
                 if let EventKind::Modify(_) = event.kind {
                     match load_config() {
                         Ok(new_config) => {
-                            println!("Updating config with {new_config}");
+                            tracing::info!("updating config with {new_config}");
                             *config.lock().await = new_config;
                         }
-                        Err(err) => println!("Error loading configuration: {err:?}"),
+                        Err(err) => tracing::warn!("error loading configuration: {err:?}"),
                     }
                 }
             }
-            Err(err) => println!("Error: {err:?}"),
+            Err(err) => tracing::error!("config watch error: {err:?}"),
         }
     }
 
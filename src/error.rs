@@ -1,6 +1,8 @@
 use std::{env::VarError, net::AddrParseError, num::ParseIntError};
 
 use lettre::address::AddressError;
+
+use crate::report::mc::bytes::DecodeError;
 use serde::Serialize;
 
 #[derive(Debug, thiserror::Error)]
@@ -23,6 +25,10 @@ pub enum Error {
     Address(#[from] AddressError),
     #[error("int parsing error: {0}")]
     ParseInt(#[from] ParseIntError),
+    #[error("packet decoding error: {0}")]
+    Decode(#[from] DecodeError),
+    #[error("base64 decoding error: {0}")]
+    Base64(#[from] base64::DecodeError),
 }
 
 impl Serialize for Error {
@@ -0,0 +1,7 @@
+pub mod error;
+pub mod logging;
+pub mod metrics;
+pub mod report;
+pub mod response;
+pub mod send_email;
+pub mod ws;
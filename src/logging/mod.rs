@@ -0,0 +1,246 @@
+//! Where logs go and how they look, configured from the environment.
+
+pub mod request;
+
+use std::{path::PathBuf, str::FromStr};
+
+use tracing::Subscriber;
+use tracing_appender::rolling::RollingFileAppender;
+use tracing_subscriber::{
+    fmt::MakeWriter, prelude::__tracing_subscriber_SubscriberExt, registry::LookupSpan,
+    util::SubscriberInitExt, EnvFilter, Layer,
+};
+
+use crate::error::Error;
+
+/// Everything at `debug` and above, as before logging was configurable.
+const DEFAULT_FILTER: &str = "debug";
+const DEFAULT_MAX_FILES: usize = 7;
+const LOG_FILE_PREFIX: &str = "backend";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human readable lines.
+    Text,
+    /// One JSON object per line.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = Error;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(Error::Generic(format!("unknown log format `{format}`"))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rotation {
+    Hourly,
+    Daily,
+}
+
+impl FromStr for Rotation {
+    type Err = Error;
+
+    fn from_str(rotation: &str) -> Result<Self, Self::Err> {
+        match rotation {
+            "hourly" => Ok(Rotation::Hourly),
+            "daily" => Ok(Rotation::Daily),
+            _ => Err(Error::Generic(format!("unknown log rotation `{rotation}`"))),
+        }
+    }
+}
+
+impl From<Rotation> for tracing_appender::rolling::Rotation {
+    fn from(rotation: Rotation) -> Self {
+        match rotation {
+            Rotation::Hourly => Self::HOURLY,
+            Rotation::Daily => Self::DAILY,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LogFileConfig {
+    pub dir: PathBuf,
+    pub rotation: Rotation,
+    /// Files kept, the oldest are deleted on rotation. `0` keeps every file.
+    pub max_files: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct LogConfig {
+    /// Directives in the `RUST_LOG` syntax, e.g. `info,dev_null_backend=debug`.
+    pub filter: String,
+    pub format: LogFormat,
+    /// Also writes to rotating files when set.
+    pub file: Option<LogFileConfig>,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            filter: DEFAULT_FILTER.to_owned(),
+            format: LogFormat::Text,
+            file: None,
+        }
+    }
+}
+
+impl LogConfig {
+    /// Reads `RUST_LOG`, `LOG_FORMAT` (`text` or `json`) and `LOG_DIR`, along
+    /// with `LOG_ROTATION` (`hourly` or `daily`) and `LOG_MAX_FILES` for the
+    /// files in it.
+    pub fn from_env() -> Result<Self, Error> {
+        let mut config = Self::default();
+        if let Ok(filter) = std::env::var("RUST_LOG") {
+            config.filter = filter;
+        }
+        if let Ok(format) = std::env::var("LOG_FORMAT") {
+            config.format = format.trim().parse()?;
+        }
+        if let Ok(dir) = std::env::var("LOG_DIR") {
+            let rotation = match std::env::var("LOG_ROTATION") {
+                Ok(rotation) => rotation.trim().parse()?,
+                Err(_) => Rotation::Daily,
+            };
+            let max_files = match std::env::var("LOG_MAX_FILES") {
+                Ok(max_files) => max_files.trim().parse()?,
+                Err(_) => DEFAULT_MAX_FILES,
+            };
+            config.file = Some(LogFileConfig {
+                dir: dir.into(),
+                rotation,
+                max_files,
+            });
+        }
+        Ok(config)
+    }
+}
+
+fn format_layer<S, W>(format: LogFormat, ansi: bool, writer: W) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = tracing_subscriber::fmt::layer().with_writer(writer);
+    match format {
+        LogFormat::Text => layer.with_ansi(ansi).boxed(),
+        LogFormat::Json => layer.json().boxed(),
+    }
+}
+
+/// Installs the global subscriber, logging to stdout and the files if any.
+pub fn init(config: &LogConfig) -> Result<(), Error> {
+    let filter = EnvFilter::try_new(&config.filter)
+        .map_err(|err| Error::Generic(format!("invalid log filter: {err}")))?;
+    let file_layer = match &config.file {
+        Some(file) => {
+            // named `backend.<date>.log`, old files are pruned on rotation
+            let writer = RollingFileAppender::builder()
+                .rotation(file.rotation.into())
+                .filename_prefix(LOG_FILE_PREFIX)
+                .filename_suffix("log")
+                .max_log_files(file.max_files)
+                .build(&file.dir)
+                .map_err(|err| Error::Generic(format!("failed to open the log files: {err}")))?;
+            Some(format_layer(config.format, false, writer))
+        }
+        None => None,
+    };
+
+    tracing_subscriber::registry()
+        .with(format_layer(config.format, true, std::io::stdout))
+        .with(file_layer)
+        .with(filter)
+        .try_init()
+        .map_err(|err| Error::Generic(format!("logging already initialized: {err}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        sync::{Arc, Mutex},
+    };
+
+    use serde_json::Value;
+    use tracing_subscriber::Registry;
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn json_lines() {
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let subscriber =
+            Registry::default().with(format_layer(LogFormat::Json, false, move || writer.clone()));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!(
+                "ws",
+                request_id = "abc-1",
+                connection = tracing::field::Empty
+            );
+            let _entered = span.enter();
+            span.record("connection", 7);
+            tracing::info!(players = 3, "server \"online\"");
+        });
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let line: Value = serde_json::from_str(output.trim()).unwrap();
+        assert_eq!(line["level"], "INFO");
+        assert_eq!(line["fields"]["message"], "server \"online\"");
+        assert_eq!(line["fields"]["players"], 3);
+        assert_eq!(line["spans"][0]["name"], "ws");
+        assert_eq!(line["spans"][0]["request_id"], "abc-1");
+        assert_eq!(line["spans"][0]["connection"], 7);
+    }
+
+    #[test]
+    fn request_span_leaves_out_query() {
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let subscriber =
+            Registry::default().with(format_layer(LogFormat::Json, false, move || writer.clone()));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let request = axum::http::Request::get("/ws?token=secret")
+                .body(())
+                .unwrap();
+            let span = request::request_span(&request);
+            let _entered = span.enter();
+            tracing::info!("upgrading");
+        });
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let line: Value = serde_json::from_str(output.trim()).unwrap();
+        assert_eq!(line["span"]["path"], "/ws");
+        assert!(!output.contains("secret"));
+    }
+
+    #[test]
+    fn format_names() {
+        assert_eq!("json".parse::<LogFormat>().unwrap(), LogFormat::Json);
+        assert_eq!("daily".parse::<Rotation>().unwrap(), Rotation::Daily);
+        assert!("yaml".parse::<LogFormat>().is_err());
+    }
+}
@@ -0,0 +1,89 @@
+//! Request ids, tying together the logs of a request and of the WebSocket or
+//! event stream it opens.
+
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    http::{header::HeaderName, HeaderValue, Request},
+    middleware::Next,
+    response::Response,
+};
+use lazy_static::lazy_static;
+use tracing::Span;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longest id taken over from a client or proxy.
+const MAX_REQUEST_ID_LEN: usize = 64;
+
+lazy_static! {
+    /// Start of the process, in hex unix millis, keeping ids unique across
+    /// restarts.
+    static ref ID_PREFIX: String = format!(
+        "{:x}",
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_millis())
+    );
+}
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Id of the request being handled, available as an extension.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+impl RequestId {
+    fn next() -> Self {
+        Self(format!(
+            "{}-{}",
+            *ID_PREFIX,
+            NEXT_ID.fetch_add(1, Ordering::Relaxed)
+        ))
+    }
+
+    /// The id sent by the client or a proxy in front, if it is usable.
+    fn from_header(value: &HeaderValue) -> Option<Self> {
+        value
+            .to_str()
+            .ok()
+            .filter(|id| !id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN)
+            .map(|id| Self(id.to_owned()))
+    }
+}
+
+/// Middleware giving every request an id, sent back in `x-request-id`.
+pub async fn assign_request_id<B>(mut request: Request<B>, next: Next<B>) -> Response {
+    let header = HeaderName::from_static(REQUEST_ID_HEADER);
+    let id = request
+        .headers()
+        .get(&header)
+        .and_then(RequestId::from_header)
+        .unwrap_or_else(RequestId::next);
+    request.extensions_mut().insert(id.clone());
+
+    let mut response = next.run(request).await;
+    if let Ok(value) = HeaderValue::from_str(&id.0) {
+        response.headers_mut().insert(header, value);
+    }
+    response
+}
+
+/// Span of a request for `TraceLayer`, carrying the id set by
+/// [`assign_request_id`]. Only the path is logged, the query may hold a
+/// `token`.
+pub fn request_span<B>(request: &Request<B>) -> Span {
+    let id = request
+        .extensions()
+        .get::<RequestId>()
+        .map_or("", |id| id.0.as_str());
+    tracing::info_span!(
+        "request",
+        id,
+        method = %request.method(),
+        path = %request.uri().path(),
+    )
+}
@@ -1,44 +1,105 @@
-mod error;
-mod report;
-mod response;
-mod send_email;
-mod ws;
-
 use std::net::SocketAddr;
 
 use axum::{
-    extract::Path,
+    extract::{Path, Query, State},
+    headers::{authorization::Bearer, Authorization},
+    http::{header, HeaderMap, StatusCode},
+    middleware,
+    response::Response as HttpResponse,
     routing::{get, post},
-    Json, Router,
+    Json, Router, TypedHeader,
 };
-use report::mc::packet::{ping::PingResponse, status::StatusResponse};
-use response::Response;
-use send_email::SendEmailData;
-use tower_http::cors::CorsLayer;
-use tracing_subscriber::{
-    fmt::writer::MakeWriterExt, prelude::__tracing_subscriber_SubscriberExt,
-    util::SubscriberInitExt,
+use dev_null_backend::{
+    error::Error,
+    logging::{
+        self,
+        request::{assign_request_id, request_span},
+        LogConfig,
+    },
+    metrics::{self, METRICS},
+    report::{
+        self,
+        mc::{
+            bedrock::BedrockStatus,
+            history::{DailyPeak, DailyPeaksQuery, History, HistoryQuery, HistoryReport},
+            modpack::ModpackDiff,
+            packet::{ping::PingResponse, status::StatusResponse},
+            query::FullStat,
+            rcon::{self, RconOutput, RconRequest, SharedRcon},
+            sessions::{LastSeen, PlayerPlaytime, Sessions, TopPlayersQuery},
+        },
+    },
+    response::Response,
+    send_email::{self, SendEmailData},
+    ws::{
+        self,
+        auth::AuthConfig,
+        handler::WsState,
+        limits::WsLimits,
+        management::{Command, CommandResponse, Manager, ManagerConfig},
+        minecraft::MinecraftJobConfig,
+    },
 };
+use tower_http::{cors::CorsLayer, trace::TraceLayer};
 
 // use crate::ws::{handler::ws_handler, management::WsManager};
 
 #[tokio::main(flavor = "multi_thread", worker_threads = 4)]
 async fn main() {
-    tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::fmt::layer()
-                .with_writer(std::io::stdout.with_max_level(tracing::Level::DEBUG)),
-        )
-        .init();
-
     dotenv::dotenv().ok();
 
-    let app = Router::new()
+    let log_config = LogConfig::from_env().expect("invalid logging configuration");
+    logging::init(&log_config).expect("logging could not be initialized");
+
+    let history = History::from_env().expect("invalid minecraft history configuration");
+    let sessions = Sessions::from_env().expect("failed to open the minecraft sessions log");
+    let manager = Manager::with_config(ManagerConfig {
+        minecraft: MinecraftJobConfig {
+            history: history.clone(),
+            sessions: sessions.clone(),
+            ..Default::default()
+        },
+        auth: AuthConfig::from_env().expect("invalid websocket token configuration"),
+        ..Default::default()
+    });
+
+    let history_routes = Router::new()
+        .route("/report/mc/history", get(mc_server_history))
+        .route("/report/mc/history/peaks", get(mc_server_daily_peaks))
+        .with_state(history);
+
+    let session_routes = Router::new()
+        .route("/report/mc/players/top", get(mc_top_players))
+        .route("/report/mc/players/:player", get(mc_player_last_seen))
+        .with_state(sessions);
+
+    let ws_limits = WsLimits::from_env().expect("invalid websocket limits configuration");
+    let ws_routes = Router::new()
         .route("/ws", get(ws::handler::ws_handler))
+        .with_state(WsState::new(manager.clone(), ws_limits));
+
+    let rcon_routes = Router::new()
+        .route("/report/mc/rcon", post(mc_server_rcon))
+        .with_state(SharedRcon::default());
+
+    let app = Router::new()
+        .route("/events", get(ws::sse::sse_handler))
         .route("/send_email", post(send_email))
         .route("/report/mc/ping/:payload", get(mc_server_ping))
         .route("/report/mc/status", get(mc_server_status))
-        .with_state(ws::management::Manager::new())
+        .route("/report/mc/query", get(mc_server_query))
+        .route("/report/mc/bedrock", get(mc_bedrock_status))
+        .route("/report/mc/modpack", get(mc_server_modpack))
+        .route(report::mc::favicon::FAVICON_URL, get(mc_server_favicon))
+        .with_state(manager)
+        .merge(ws_routes)
+        .merge(history_routes)
+        .merge(session_routes)
+        .merge(rcon_routes)
+        .route("/metrics", get(metrics_text))
+        .route_layer(middleware::from_fn(metrics::track_requests))
+        .layer(TraceLayer::new_for_http().make_span_with(request_span))
+        .layer(middleware::from_fn(assign_request_id))
         .layer(CorsLayer::permissive());
 
     let ip = std::env::var("BACKEND_ADDR").expect("cannot run without specified address");
@@ -53,14 +114,92 @@ async fn main() {
         .unwrap();
 }
 
+async fn metrics_text() -> ([(header::HeaderName, &'static str); 1], String) {
+    (
+        [(header::CONTENT_TYPE, metrics::CONTENT_TYPE)],
+        METRICS.render(),
+    )
+}
+
 async fn send_email(Json(send_email_data): Json<SendEmailData>) -> Json<Response<()>> {
     Json(send_email::send_email(send_email_data))
 }
 
 async fn mc_server_ping(Path(payload): Path<i64>) -> Json<Response<PingResponse>> {
-    Json(report::mc::ping_route(payload))
+    Json(report::mc::ping_route(payload).await)
 }
 
 async fn mc_server_status() -> Json<Response<StatusResponse>> {
-    Json(report::mc::status_route())
+    Json(report::mc::status_route().await)
+}
+
+async fn mc_server_query() -> Json<Response<FullStat>> {
+    Json(report::mc::query_route().await)
+}
+
+async fn mc_server_history(
+    State(history): State<Option<History>>,
+    Query(query): Query<HistoryQuery>,
+) -> Json<Response<HistoryReport>> {
+    Json(report::mc::history::history_route(history, query).await)
+}
+
+async fn mc_server_daily_peaks(
+    State(history): State<Option<History>>,
+    Query(query): Query<DailyPeaksQuery>,
+) -> Json<Response<Vec<DailyPeak>>> {
+    Json(report::mc::history::daily_peaks_route(history, query).await)
+}
+
+async fn mc_top_players(
+    State(sessions): State<Option<Sessions>>,
+    Query(query): Query<TopPlayersQuery>,
+) -> Json<Response<Vec<PlayerPlaytime>>> {
+    Json(report::mc::sessions::top_players_route(
+        sessions.as_ref(),
+        query,
+    ))
+}
+
+async fn mc_player_last_seen(
+    State(sessions): State<Option<Sessions>>,
+    Path(player): Path<String>,
+) -> Json<Response<LastSeen>> {
+    Json(report::mc::sessions::last_seen_route(
+        sessions.as_ref(),
+        &player,
+    ))
+}
+
+async fn mc_server_modpack() -> Json<Response<ModpackDiff>> {
+    Json(report::mc::modpack_route().await)
+}
+
+async fn mc_bedrock_status() -> Json<Response<BedrockStatus>> {
+    Json(report::mc::bedrock_route().await)
+}
+
+async fn mc_server_favicon(State(manager): State<Manager>, headers: HeaderMap) -> HttpResponse {
+    let favicon = match manager.send_command(Command::Favicon).await {
+        Some(CommandResponse::Favicon(favicon)) => favicon,
+        _ => None,
+    };
+    report::mc::favicon::favicon_route(favicon, headers.get(header::IF_NONE_MATCH))
+}
+
+async fn mc_server_rcon(
+    State(rcon): State<SharedRcon>,
+    authorization: Option<TypedHeader<Authorization<Bearer>>>,
+    Json(request): Json<RconRequest>,
+) -> (StatusCode, Json<Response<RconOutput>>) {
+    let token = authorization.as_ref().map(|auth| auth.token());
+    if !rcon::is_authorized(token) {
+        let err = Error::Generic("unauthorized".to_owned());
+        return (StatusCode::UNAUTHORIZED, Json(Response::err(err)));
+    }
+
+    (
+        StatusCode::OK,
+        Json(rcon::command_route(rcon, request).await),
+    )
 }
@@ -0,0 +1,231 @@
+//! Process wide metrics, rendered in the Prometheus text format on
+//! `/metrics`.
+
+use std::time::Instant;
+
+use axum::{extract::MatchedPath, http::Request, middleware::Next, response::Response};
+use lazy_static::lazy_static;
+use prometheus::{
+    core::Collector, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, Opts,
+    Registry, TextEncoder,
+};
+
+pub const CONTENT_TYPE: &str = prometheus::TEXT_FORMAT;
+
+/// Bucket bounds in seconds, from fast HTTP handlers to slow server polls.
+const LATENCY_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+pub struct Metrics {
+    registry: Registry,
+    pub http_requests: IntCounterVec,
+    pub http_request_duration: HistogramVec,
+    pub websocket_connections: IntGauge,
+    pub channel_subscriptions: IntGaugeVec,
+    pub polls: IntCounterVec,
+    pub poll_duration: HistogramVec,
+    pub server_online: IntGaugeVec,
+    pub players_online: IntGaugeVec,
+    pub players_max: IntGaugeVec,
+    pub emails: IntCounterVec,
+}
+
+/// Adds a metric to the registry, the names are fixed so this can't fail
+/// short of a typo.
+fn register<C: Collector + Clone + 'static>(registry: &Registry, collector: C) -> C {
+    registry
+        .register(Box::new(collector.clone()))
+        .expect("metric should be valid and registered once");
+    collector
+}
+
+fn counters(registry: &Registry, name: &str, help: &str, labels: &[&str]) -> IntCounterVec {
+    let counters = IntCounterVec::new(Opts::new(name, help), labels).expect("valid counter");
+    register(registry, counters)
+}
+
+fn gauges(registry: &Registry, name: &str, help: &str, labels: &[&str]) -> IntGaugeVec {
+    let gauges = IntGaugeVec::new(Opts::new(name, help), labels).expect("valid gauge");
+    register(registry, gauges)
+}
+
+fn histograms(registry: &Registry, name: &str, help: &str, labels: &[&str]) -> HistogramVec {
+    let opts = HistogramOpts::new(name, help).buckets(LATENCY_BUCKETS.to_vec());
+    let histograms = HistogramVec::new(opts, labels).expect("valid histogram");
+    register(registry, histograms)
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new();
+        let websocket_connections = IntGauge::new(
+            "websocket_connections",
+            "WebSocket connections currently open.",
+        )
+        .expect("valid gauge");
+        Self {
+            http_requests: counters(
+                &registry,
+                "http_requests_total",
+                "HTTP requests handled, by route and status.",
+                &["method", "route", "status"],
+            ),
+            http_request_duration: histograms(
+                &registry,
+                "http_request_duration_seconds",
+                "Time spent handling HTTP requests, by route.",
+                &["method", "route"],
+            ),
+            websocket_connections: register(&registry, websocket_connections),
+            channel_subscriptions: gauges(
+                &registry,
+                "channel_subscriptions",
+                "Subscriptions currently open, by channel.",
+                &["channel"],
+            ),
+            polls: counters(
+                &registry,
+                "server_polls_total",
+                "Polls of the game servers, by outcome.",
+                &["server", "poll", "outcome"],
+            ),
+            poll_duration: histograms(
+                &registry,
+                "server_poll_duration_seconds",
+                "Time taken by the polls of the game servers.",
+                &["server", "poll"],
+            ),
+            server_online: gauges(
+                &registry,
+                "server_online",
+                "Whether the game server answered its last poll.",
+                &["server"],
+            ),
+            players_online: gauges(
+                &registry,
+                "server_players_online",
+                "Players online on the game server.",
+                &["server"],
+            ),
+            players_max: gauges(
+                &registry,
+                "server_players_max",
+                "Player slots of the game server.",
+                &["server"],
+            ),
+            emails: counters(
+                &registry,
+                "emails_sent_total",
+                "Contact emails sent, by outcome.",
+                &["outcome"],
+            ),
+            registry,
+        }
+    }
+
+    pub fn render(&self) -> String {
+        TextEncoder::new()
+            .encode_to_string(&self.registry.gather())
+            .unwrap_or_else(|err| {
+                tracing::error!("failed to render metrics: {err}");
+                String::new()
+            })
+    }
+
+    /// Counts a poll of `server` and how long it took.
+    pub fn record_poll<T, E>(
+        &self,
+        server: &str,
+        poll: &str,
+        start: Instant,
+        result: &Result<T, E>,
+    ) {
+        let outcome = if result.is_ok() { "success" } else { "failure" };
+        self.polls.with_label_values(&[server, poll, outcome]).inc();
+        self.poll_duration
+            .with_label_values(&[server, poll])
+            .observe(start.elapsed().as_secs_f64());
+    }
+
+    pub fn record_email<T, E>(&self, result: &Result<T, E>) {
+        let outcome = if result.is_ok() { "success" } else { "failure" };
+        self.emails.with_label_values(&[outcome]).inc();
+    }
+}
+
+lazy_static! {
+    pub static ref METRICS: Metrics = Metrics::new();
+}
+
+/// Middleware counting requests and their latency per matched route, so that
+/// path parameters do not make a series per value. Meant for `route_layer`,
+/// which only runs it for requests that matched a route.
+pub async fn track_requests<B>(request: Request<B>, next: Next<B>) -> Response {
+    let Some(route) = request.extensions().get::<MatchedPath>().cloned() else {
+        return next.run(request).await;
+    };
+    let start = Instant::now();
+    let method = request.method().to_string();
+
+    let response = next.run(request).await;
+
+    let status = response.status().as_u16().to_string();
+    METRICS
+        .http_requests
+        .with_label_values(&[&method, route.as_str(), &status])
+        .inc();
+    METRICS
+        .http_request_duration
+        .with_label_values(&[&method, route.as_str()])
+        .observe(start.elapsed().as_secs_f64());
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_format() {
+        let metrics = Metrics::new();
+        metrics
+            .polls
+            .with_label_values(&["minecraft", "status", "success"])
+            .inc();
+        metrics
+            .polls
+            .with_label_values(&["minecraft", "status", "success"])
+            .inc();
+        metrics
+            .server_online
+            .with_label_values(&["minecraft"])
+            .set(1);
+        metrics.websocket_connections.inc();
+        metrics.websocket_connections.inc();
+        metrics.websocket_connections.dec();
+        metrics
+            .poll_duration
+            .with_label_values(&["minecraft", "status"])
+            .observe(0.02);
+        metrics.emails.with_label_values(&["fail\"ure"]).inc();
+
+        let text = metrics.render();
+        assert!(text.contains("# TYPE server_polls_total counter\n"));
+        assert!(text.contains(
+            "server_polls_total{outcome=\"success\",poll=\"status\",server=\"minecraft\"} 2\n"
+        ));
+        assert!(text.contains("server_online{server=\"minecraft\"} 1\n"));
+        assert!(text.contains("websocket_connections 1\n"));
+        assert!(text.contains(
+            "server_poll_duration_seconds_bucket{poll=\"status\",server=\"minecraft\",le=\"0.01\"} 0\n"
+        ));
+        assert!(text.contains(
+            "server_poll_duration_seconds_bucket{poll=\"status\",server=\"minecraft\",le=\"0.025\"} 1\n"
+        ));
+        assert!(text.contains(
+            "server_poll_duration_seconds_count{poll=\"status\",server=\"minecraft\"} 1\n"
+        ));
+        assert!(text.contains("emails_sent_total{outcome=\"fail\\\"ure\"} 1\n"));
+    }
+}
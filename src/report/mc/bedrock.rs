@@ -0,0 +1,223 @@
+//! Bedrock Edition status through a RakNet Unconnected Ping, answered by
+//! Bedrock Dedicated Server and Geyser. See <https://wiki.vg/Raknet_Protocol>.
+
+use std::{
+    net::{Ipv4Addr, SocketAddr, UdpSocket},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use bytes::BufMut;
+use serde::Serialize;
+
+use crate::error::Error;
+
+use super::{
+    bytes::Bytes,
+    latency::{millis_since, Latency},
+    Result,
+};
+
+const UNCONNECTED_PING: u8 = 0x01;
+const UNCONNECTED_PONG: u8 = 0x1C;
+/// Marks offline messages, sent as is by every RakNet implementation.
+const OFFLINE_MESSAGE_DATA_ID: [u8; 16] = [
+    0x00, 0xFF, 0xFF, 0x00, 0xFE, 0xFE, 0xFE, 0xFE, 0xFD, 0xFD, 0xFD, 0xFD, 0x12, 0x34, 0x56, 0x78,
+];
+const DEFAULT_BEDROCK_PORT: u16 = 19132;
+const MAX_DATAGRAM_SIZE: usize = 1500;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BedrockStatus {
+    /// `MCPE` for Bedrock, `MCEE` for Education Edition.
+    pub edition: String,
+    pub motd: String,
+    pub protocol: i32,
+    pub version: String,
+    pub players_online: i32,
+    pub players_max: i32,
+    pub server_guid: String,
+    pub sub_motd: Option<String>,
+    pub game_mode: Option<String>,
+    pub game_mode_id: Option<i32>,
+    pub port_v4: Option<u16>,
+    pub port_v6: Option<u16>,
+    pub latency: Latency,
+}
+
+/// Whether a Bedrock server is set up, through `MC_BEDROCK_ADDR` or, for a
+/// Geyser server next to the Java one, `MC_BEDROCK_PORT`. [`bedrock_addr`]
+/// alone would fall back to the Java server, which may not speak Bedrock.
+pub fn is_configured() -> bool {
+    std::env::var_os("MC_BEDROCK_ADDR").is_some() || std::env::var_os("MC_BEDROCK_PORT").is_some()
+}
+
+/// Address of the Bedrock server, `MC_BEDROCK_ADDR` (defaulting to
+/// `MC_SERVER_ADDR`) and `MC_BEDROCK_PORT` (defaulting to 19132).
+pub fn bedrock_addr() -> Result<SocketAddr> {
+    let ip = std::env::var("MC_BEDROCK_ADDR")
+        .or_else(|_| std::env::var("MC_SERVER_ADDR"))?
+        .parse()?;
+    let port = match std::env::var("MC_BEDROCK_PORT") {
+        Ok(port) => port.parse()?,
+        Err(_) => DEFAULT_BEDROCK_PORT,
+    };
+
+    Ok(SocketAddr::new(ip, port))
+}
+
+pub fn status() -> Result<BedrockStatus> {
+    status_at(bedrock_addr()?)
+}
+
+pub fn status_at(addr: SocketAddr) -> Result<BedrockStatus> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+    let five_sec = Duration::from_secs(5);
+    socket.set_read_timeout(Some(five_sec))?;
+    socket.set_write_timeout(Some(five_sec))?;
+    socket.connect(addr)?;
+
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as i64)
+        .unwrap_or_default();
+    let mut request: Bytes = vec![].into();
+    request.put_u8(UNCONNECTED_PING);
+    request.put_i64(time);
+    request.put_slice(&OFFLINE_MESSAGE_DATA_ID);
+    request.put_i64(rand_guid());
+    let request: Vec<u8> = request.into();
+
+    let start = Instant::now();
+    socket.send(&request)?;
+    let mut buffer = vec![0u8; MAX_DATAGRAM_SIZE];
+    let size = socket.recv(&mut buffer)?;
+    let ping_ms = millis_since(start);
+    buffer.truncate(size);
+
+    let mut response: Bytes = buffer.into();
+    if response.get_u8()? != UNCONNECTED_PONG || response.get_i64()? != time {
+        return Err(Error::Generic(
+            "unexpected bedrock ping response".to_owned(),
+        ));
+    }
+    response.skip(8)?;
+    if response.get_bytes(OFFLINE_MESSAGE_DATA_ID.len())? != OFFLINE_MESSAGE_DATA_ID {
+        return Err(Error::Generic("invalid raknet offline message".to_owned()));
+    }
+    let length = response.get_u16()?;
+    let server_id = response.get_bytes(length.into())?;
+
+    let mut status = parse_server_id(&String::from_utf8_lossy(&server_id))?;
+    status.latency.ping_ms = Some(ping_ms);
+    Ok(status)
+}
+
+/// Clients identify themselves with a random guid, it only has to differ
+/// between clients.
+fn rand_guid() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.subsec_nanos() as i64 ^ i64::from(std::process::id()) << 32)
+        .unwrap_or_default()
+}
+
+/// Parses the server id string, e.g.
+/// `MCPE;Dedicated Server;594;1.20.1;0;10;13253860892328930865;Bedrock level;Survival;1;19132;19133;`
+/// Older servers stop after the player counts or the guid.
+fn parse_server_id(server_id: &str) -> Result<BedrockStatus> {
+    let fields: Vec<&str> = server_id.split(';').collect();
+    if fields.len() < 6 {
+        return Err(Error::Generic(format!(
+            "bedrock server id `{server_id}` has too few fields"
+        )));
+    }
+    let field = |index: usize| {
+        fields
+            .get(index)
+            .filter(|field| !field.is_empty())
+            .map(|field| field.to_string())
+    };
+
+    Ok(BedrockStatus {
+        edition: fields[0].to_owned(),
+        motd: fields[1].to_owned(),
+        protocol: fields[2].parse()?,
+        version: fields[3].to_owned(),
+        players_online: fields[4].parse()?,
+        players_max: fields[5].parse()?,
+        server_guid: field(6).unwrap_or_default(),
+        sub_motd: field(7),
+        game_mode: field(8),
+        game_mode_id: field(9).and_then(|id| id.parse().ok()),
+        port_v4: field(10).and_then(|port| port.parse().ok()),
+        port_v6: field(11).and_then(|port| port.parse().ok()),
+        latency: Latency::default(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    const SERVER_ID: &str =
+        "MCPE;Dedicated Server;594;1.20.1;2;10;13253860892328930865;Bedrock level;Survival;1;19132;19133;";
+
+    fn spawn_server(server_id: &'static str) -> SocketAddr {
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = socket.local_addr().unwrap();
+
+        thread::spawn(move || {
+            let mut buffer = [0u8; 64];
+            let (size, client) = socket.recv_from(&mut buffer).unwrap();
+            let mut request: Bytes = buffer[..size].into();
+            assert_eq!(request.get_u8().unwrap(), UNCONNECTED_PING);
+            let time = request.get_i64().unwrap();
+            let magic = request.get_bytes(16).unwrap();
+            assert_eq!(magic, OFFLINE_MESSAGE_DATA_ID);
+
+            let mut response = vec![UNCONNECTED_PONG];
+            response.extend(time.to_be_bytes());
+            response.extend(42i64.to_be_bytes());
+            response.extend(OFFLINE_MESSAGE_DATA_ID);
+            response.extend((server_id.len() as u16).to_be_bytes());
+            response.extend(server_id.as_bytes());
+            socket.send_to(&response, client).unwrap();
+        });
+
+        addr
+    }
+
+    #[test]
+    fn unconnected_ping() {
+        let addr = spawn_server(SERVER_ID);
+
+        let status = status_at(addr).unwrap();
+        assert_eq!(status.edition, "MCPE");
+        assert_eq!(status.motd, "Dedicated Server");
+        assert_eq!(status.protocol, 594);
+        assert_eq!(status.version, "1.20.1");
+        assert_eq!(status.players_online, 2);
+        assert_eq!(status.players_max, 10);
+        assert_eq!(status.sub_motd.as_deref(), Some("Bedrock level"));
+        assert_eq!(status.game_mode.as_deref(), Some("Survival"));
+        assert_eq!(status.game_mode_id, Some(1));
+        assert_eq!(status.port_v4, Some(19132));
+        assert_eq!(status.port_v6, Some(19133));
+        assert!(status.latency.ping_ms.is_some());
+    }
+
+    #[test]
+    fn short_server_id() {
+        let status = parse_server_id("MCPE;Geyser;389;1.14.60;0;100").unwrap();
+        assert_eq!(status.motd, "Geyser");
+        assert_eq!(status.players_max, 100);
+        assert_eq!(status.server_guid, "");
+        assert_eq!(status.game_mode, None);
+
+        assert!(parse_server_id("MCPE;Geyser;389").is_err());
+        assert!(parse_server_id("MCPE;Geyser;abc;1.14.60;0;100").is_err());
+    }
+}
@@ -1,7 +1,6 @@
 use bytes::{Buf, BufMut};
 
-// use super::status_packet::ResourceLocation;
-pub type Varint = i64;
+use super::varint::VarInt;
 
 #[derive(Debug)]
 pub struct Bytes(bytes::BytesMut);
@@ -19,20 +18,6 @@ unsafe impl BufMut for Bytes {
     }
 }
 
-impl Buf for Bytes {
-    fn remaining(&self) -> usize {
-        self.0.remaining()
-    }
-
-    fn chunk(&self) -> &[u8] {
-        self.0.chunk()
-    }
-
-    fn advance(&mut self, cnt: usize) {
-        self.0.advance(cnt)
-    }
-}
-
 impl std::io::Write for Bytes {
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
         self.put_slice(buf);
@@ -48,37 +33,111 @@ impl Bytes {
     pub fn len(&self) -> usize {
         self.0.len()
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Errors produced while reading from [`Bytes`], so that a malformed or
+/// truncated packet is reported instead of panicking.
+#[derive(Debug, thiserror::Error)]
+pub enum DecodeError {
+    #[error("unexpected end of data: needed {needed} bytes, {remaining} remaining")]
+    UnexpectedEof { needed: usize, remaining: usize },
+    #[error("variable length number is longer than {0} bytes")]
+    VarNumTooLong(usize),
+    #[error("invalid length {0}")]
+    InvalidLength(i64),
+    #[error("invalid resource location `{0}`")]
+    InvalidResourceLocation(String),
+    #[error("string is missing its null terminator")]
+    UnterminatedString,
 }
 
 impl Bytes {
-    pub fn get_bool(&mut self) -> bool {
-        self.get_u8() > 0
+    fn ensure_remaining(&self, needed: usize) -> Result<(), DecodeError> {
+        let remaining = self.0.remaining();
+        if remaining < needed {
+            return Err(DecodeError::UnexpectedEof { needed, remaining });
+        }
+        Ok(())
     }
 
-    pub fn get_varint(&mut self) -> Varint {
-        let mut bytes = vec![];
-        let mut byte = self.get_u8();
-        bytes.push(byte);
-        while byte & 0x80 != 0 {
-            byte = self.get_u8();
-            bytes.push(byte);
-        }
+    pub fn get_u8(&mut self) -> Result<u8, DecodeError> {
+        self.ensure_remaining(1)?;
+        Ok(self.0.get_u8())
+    }
+
+    pub fn get_u16(&mut self) -> Result<u16, DecodeError> {
+        self.ensure_remaining(2)?;
+        Ok(self.0.get_u16())
+    }
 
-        leb128::read::signed(&mut &bytes[..]).expect("should be varint")
+    pub fn get_u16_le(&mut self) -> Result<u16, DecodeError> {
+        self.ensure_remaining(2)?;
+        Ok(self.0.get_u16_le())
     }
 
-    pub fn get_string(&mut self) -> String {
-        let string_length = self.get_varint();
-        let mut string_bytes = vec![0u8; string_length as usize];
-        self.copy_to_slice(&mut string_bytes[..]);
+    pub fn get_i32(&mut self) -> Result<i32, DecodeError> {
+        self.ensure_remaining(4)?;
+        Ok(self.0.get_i32())
+    }
 
-        String::from_utf8_lossy(string_bytes.as_slice()).into()
+    pub fn get_i64(&mut self) -> Result<i64, DecodeError> {
+        self.ensure_remaining(8)?;
+        Ok(self.0.get_i64())
+    }
+
+    pub fn get_bool(&mut self) -> Result<bool, DecodeError> {
+        Ok(self.get_u8()? > 0)
+    }
+
+    pub fn get_varint(&mut self) -> Result<i32, DecodeError> {
+        Ok(VarInt::decode(|| self.get_u8())?.0)
+    }
+
+    pub fn get_length(&mut self) -> Result<usize, DecodeError> {
+        let length = self.get_varint()?;
+        usize::try_from(length).map_err(|_| DecodeError::InvalidLength(length.into()))
+    }
+
+    pub fn get_string(&mut self) -> Result<String, DecodeError> {
+        let string_length = self.get_length()?;
+        self.ensure_remaining(string_length)?;
+        let string_bytes = self.0.split_to(string_length);
+
+        Ok(String::from_utf8_lossy(&string_bytes).into())
+    }
+
+    /// Reads a null terminated ISO-8859-1 string, as used by the Query protocol.
+    pub fn get_cstring(&mut self) -> Result<String, DecodeError> {
+        let string_length = self
+            .0
+            .iter()
+            .position(|&byte| byte == 0)
+            .ok_or(DecodeError::UnterminatedString)?;
+        let string_bytes = self.0.split_to(string_length);
+        self.0.advance(1);
+
+        Ok(string_bytes.iter().map(|&byte| byte as char).collect())
+    }
+
+    pub fn get_bytes(&mut self, count: usize) -> Result<Vec<u8>, DecodeError> {
+        self.ensure_remaining(count)?;
+        Ok(self.0.split_to(count).to_vec())
+    }
+
+    pub fn skip(&mut self, count: usize) -> Result<(), DecodeError> {
+        self.ensure_remaining(count)?;
+        self.0.advance(count);
+        Ok(())
     }
 }
 
 impl Bytes {
-    pub fn put_varint(&mut self, value: Varint) {
-        leb128::write::signed(self, value).expect("write i64 as a varint to ByteBuffer");
+    pub fn put_varint(&mut self, value: i32) {
+        VarInt(value).encode(self);
     }
 
     pub fn put_vec(&mut self, vec: &[u8]) {
@@ -90,16 +149,16 @@ impl Bytes {
         self.put_vec(&slice);
     }
 
-    pub fn put_string(&mut self, string: &String) {
+    pub fn put_string(&mut self, string: &str) {
         let string_length = string.len();
         let string_bytes = string.as_bytes();
 
-        self.put_varint(string_length as Varint);
+        self.put_varint(string_length as i32);
         self.put_slice(string_bytes);
     }
 }
 
-impl<'a> From<&'a [u8]> for Bytes {
+impl From<&[u8]> for Bytes {
     fn from(value: &[u8]) -> Self {
         Bytes(value.into())
     }
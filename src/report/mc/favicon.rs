@@ -0,0 +1,113 @@
+use axum::{
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response as HttpResponse},
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::error::Error;
+
+use super::Result;
+
+pub const FAVICON_URL: &str = "/report/mc/favicon.png";
+const DATA_URL_PREFIX: &str = "data:image/png;base64,";
+
+/// Server icon decoded from the `data:` URL in the status response. Only the
+/// URL and hash are serialized, the image itself is served by [`favicon_route`].
+#[derive(Debug, Clone, Serialize)]
+pub struct Favicon {
+    #[serde(skip)]
+    pub png: Vec<u8>,
+    pub url: String,
+    pub hash: String,
+}
+
+impl Favicon {
+    pub fn from_data_url(data_url: &str) -> Result<Self> {
+        let encoded: String = data_url
+            .strip_prefix(DATA_URL_PREFIX)
+            .ok_or_else(|| Error::Generic("favicon is not a base64 png data url".to_owned()))?
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect();
+        let png = STANDARD.decode(encoded)?;
+
+        // the first half of a SHA-256 is plenty to tell icons apart, and
+        // stays the same across restarts and toolchains
+        let hash = Sha256::digest(&png)[..16]
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+
+        Ok(Self {
+            url: format!("{FAVICON_URL}?v={hash}"),
+            png,
+            hash,
+        })
+    }
+}
+
+/// Whether an `If-None-Match` list names `etag`, comparing weakly as GET
+/// requests do.
+fn etag_matches(if_none_match: &HeaderValue, etag: &str) -> bool {
+    let Ok(if_none_match) = if_none_match.to_str() else {
+        return false;
+    };
+    if_none_match
+        .split(',')
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == etag)
+}
+
+/// Serves the icon the Minecraft job last saw, `None` when it has none yet.
+pub fn favicon_route(
+    favicon: Option<Favicon>,
+    if_none_match: Option<&HeaderValue>,
+) -> HttpResponse {
+    let Some(favicon) = favicon else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let etag = format!("\"{}\"", favicon.hash);
+    let headers = [
+        (header::ETAG, etag.clone()),
+        (header::CACHE_CONTROL, "public, max-age=300".to_owned()),
+    ];
+
+    if if_none_match.is_some_and(|value| etag_matches(value, &etag)) {
+        return (StatusCode::NOT_MODIFIED, headers).into_response();
+    }
+
+    (
+        headers,
+        [(header::CONTENT_TYPE, "image/png".to_owned())],
+        favicon.png,
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn if_none_match_lists() {
+        let etag = "\"abc\"";
+        let matches = |value: &'static str| etag_matches(&HeaderValue::from_static(value), etag);
+        assert!(matches("\"abc\""));
+        assert!(matches("W/\"abc\""));
+        assert!(matches("\"old\", W/\"abc\""));
+        assert!(matches("*"));
+        assert!(!matches("\"old\""));
+        assert!(!matches("\"abcd\""));
+    }
+
+    #[test]
+    fn stable_hash() {
+        let data_url = format!("{DATA_URL_PREFIX}{}", STANDARD.encode(b"png"));
+        let favicon = Favicon::from_data_url(&data_url).unwrap();
+        assert_eq!(favicon.hash, "8f8cbb7dcf46e0bc7d53265749a6c17d");
+        assert_eq!(favicon.url, format!("{FAVICON_URL}?v={}", favicon.hash));
+    }
+}
@@ -0,0 +1,500 @@
+//! Uptime, player count and latency history, kept as one append-only JSON lines
+//! file per day in `MC_HISTORY_DIR`.
+//!
+//! Recent days keep every poll, older days are downsampled into
+//! [`DOWNSAMPLED_RESOLUTION`] buckets and days past the retention are deleted.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{error::Error, response::Response};
+
+use super::Result;
+
+const DAY: u64 = 24 * 60 * 60;
+/// Resolution of days older than the raw retention, in seconds.
+pub const DOWNSAMPLED_RESOLUTION: u64 = 5 * 60;
+/// Most points a single report returns, the resolution is raised to fit.
+const MAX_POINTS: u64 = 2000;
+const DEFAULT_RETENTION_DAYS: u64 = 90;
+const DEFAULT_RAW_DAYS: u64 = 2;
+
+/// Result of a single poll.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sample {
+    pub online: bool,
+    pub players_online: Option<i32>,
+    pub latency_ms: Option<f64>,
+}
+
+/// Polls aggregated over the bucket starting at `time`, a raw poll is a
+/// bucket of one sample.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Point {
+    time: u64,
+    samples: u32,
+    online_samples: u32,
+    #[serde(default)]
+    player_samples: u32,
+    #[serde(default)]
+    players_sum: i64,
+    #[serde(default)]
+    players_peak: i32,
+    #[serde(default)]
+    latency_samples: u32,
+    #[serde(default)]
+    latency_sum_ms: f64,
+}
+
+impl Point {
+    fn new(time: u64, sample: Sample) -> Self {
+        let players = sample.players_online.filter(|_| sample.online);
+        let latency = sample.latency_ms.filter(|_| sample.online);
+        Self {
+            time,
+            samples: 1,
+            online_samples: sample.online.into(),
+            player_samples: players.is_some().into(),
+            players_sum: players.unwrap_or_default().into(),
+            players_peak: players.unwrap_or_default(),
+            latency_samples: latency.is_some().into(),
+            latency_sum_ms: latency.unwrap_or_default(),
+        }
+    }
+
+    fn merge(&mut self, other: &Point) {
+        self.samples += other.samples;
+        self.online_samples += other.online_samples;
+        self.player_samples += other.player_samples;
+        self.players_sum += other.players_sum;
+        self.players_peak = self.players_peak.max(other.players_peak);
+        self.latency_samples += other.latency_samples;
+        self.latency_sum_ms += other.latency_sum_ms;
+    }
+}
+
+/// Merges time ordered points into buckets of `resolution` seconds.
+fn downsample(points: impl IntoIterator<Item = Point>, resolution: u64) -> Vec<Point> {
+    let mut buckets: Vec<Point> = vec![];
+    for point in points {
+        let time = point.time - point.time % resolution;
+        match buckets.last_mut() {
+            Some(bucket) if bucket.time == time => bucket.merge(&point),
+            _ => buckets.push(Point { time, ..point }),
+        }
+    }
+    buckets
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct HistoryConfig {
+    /// Days after which history is deleted.
+    pub retention_days: u64,
+    /// Days for which every poll is kept before being downsampled.
+    pub raw_days: u64,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            retention_days: DEFAULT_RETENTION_DAYS,
+            raw_days: DEFAULT_RAW_DAYS,
+        }
+    }
+}
+
+impl HistoryConfig {
+    /// Reads `MC_HISTORY_RETENTION_DAYS` and `MC_HISTORY_RAW_DAYS`.
+    pub(super) fn from_env() -> Result<Self> {
+        let mut config = Self::default();
+        if let Ok(days) = std::env::var("MC_HISTORY_RETENTION_DAYS") {
+            config.retention_days = days.parse()?;
+        }
+        if let Ok(days) = std::env::var("MC_HISTORY_RAW_DAYS") {
+            config.raw_days = days.parse()?;
+        }
+        Ok(config)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct History {
+    inner: Arc<Mutex<HistoryInner>>,
+}
+
+#[derive(Debug)]
+struct HistoryInner {
+    dir: PathBuf,
+    config: HistoryConfig,
+    /// Day the old files were last compacted on.
+    maintained_day: Option<u64>,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+impl History {
+    /// Opens the history in `MC_HISTORY_DIR`, `None` when it isn't set.
+    pub fn from_env() -> Result<Option<Self>> {
+        match std::env::var("MC_HISTORY_DIR") {
+            Ok(dir) => Ok(Some(Self::open(dir, HistoryConfig::from_env()?)?)),
+            Err(_) => Ok(None),
+        }
+    }
+
+    pub fn open(dir: impl Into<PathBuf>, config: HistoryConfig) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Self {
+            inner: Arc::new(Mutex::new(HistoryInner {
+                dir,
+                config,
+                maintained_day: None,
+            })),
+        })
+    }
+
+    pub fn record(&self, sample: Sample) {
+        if let Err(err) = self.record_at(now(), sample) {
+            tracing::warn!("failed to record minecraft history: {err}");
+        }
+    }
+
+    pub fn record_at(&self, time: u64, sample: Sample) -> Result<()> {
+        let mut inner = self
+            .inner
+            .lock()
+            .expect("history lock should not be poisoned");
+        let day = time / DAY;
+        if inner.maintained_day != Some(day) {
+            inner.maintain(day)?;
+            inner.maintained_day = Some(day);
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(inner.day_path(day))?;
+        let mut line = serde_json::to_vec(&Point::new(time, sample))?;
+        line.push(b'\n');
+        file.write_all(&line)?;
+        Ok(())
+    }
+
+    /// Uptime and player counts between `from` and `to` (unix seconds), in
+    /// buckets of at least `resolution` seconds.
+    pub fn report(&self, from: u64, to: u64, resolution: u64) -> Result<HistoryReport> {
+        if from >= to {
+            return Err(Error::Generic("`from` has to be before `to`".to_owned()));
+        }
+        let resolution = resolution.max(1).max((to - from).div_ceil(MAX_POINTS));
+
+        // only days with a file are read, and outside the lock so the writes
+        // don't wait on a report
+        let day_paths = self
+            .inner
+            .lock()
+            .expect("history lock should not be poisoned")
+            .day_paths(from / DAY..=(to - 1) / DAY)?;
+        let mut points = vec![];
+        for path in day_paths {
+            points.extend(
+                read_points(&path)?
+                    .into_iter()
+                    .filter(|point| (from..to).contains(&point.time)),
+            );
+        }
+
+        let total = points.iter().fold(Point::default(), |mut total, point| {
+            total.merge(point);
+            total
+        });
+
+        Ok(HistoryReport {
+            from,
+            to,
+            resolution,
+            uptime_percent: uptime_percent(&total),
+            series: downsample(points, resolution)
+                .iter()
+                .map(HistoryPoint::from)
+                .collect(),
+        })
+    }
+}
+
+impl HistoryInner {
+    fn day_path(&self, day: u64) -> PathBuf {
+        self.dir.join(format!("minecraft-{day}.jsonl"))
+    }
+
+    /// Days that have a file, with the path of each.
+    fn days(&self) -> Result<Vec<(u64, PathBuf)>> {
+        let mut days = vec![];
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            let day = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_prefix("minecraft-"))
+                .and_then(|name| name.strip_suffix(".jsonl"))
+                .and_then(|day| day.parse::<u64>().ok());
+            if let Some(day) = day {
+                days.push((day, path));
+            }
+        }
+        Ok(days)
+    }
+
+    /// Paths of the days in `range` that have a file, in order.
+    fn day_paths(&self, range: RangeInclusive<u64>) -> Result<Vec<PathBuf>> {
+        let mut days = self.days()?;
+        days.retain(|(day, _)| range.contains(day));
+        days.sort_unstable_by_key(|(day, _)| *day);
+        Ok(days.into_iter().map(|(_, path)| path).collect())
+    }
+
+    /// Deletes days past the retention and downsamples the ones past the raw
+    /// retention. Downsampling already downsampled days changes nothing.
+    fn maintain(&self, today: u64) -> Result<()> {
+        for (day, path) in self.days()? {
+            let age = today.saturating_sub(day);
+            if age > self.config.retention_days {
+                tracing::debug!("deleting minecraft history of day {day}");
+                fs::remove_file(&path)?;
+            } else if age > self.config.raw_days {
+                let points = read_points(&path)?;
+                let downsampled = downsample(points.iter().copied(), DOWNSAMPLED_RESOLUTION);
+                if downsampled.len() < points.len() {
+                    tracing::debug!("downsampling minecraft history of day {day}");
+                    write_points(&path, &downsampled)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn read_points(path: &Path) -> Result<Vec<Point>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut points = vec![];
+    for line in BufReader::new(file).lines() {
+        // a crash mid-append leaves a partial last line behind
+        match serde_json::from_str(&line?) {
+            Ok(point) => points.push(point),
+            Err(err) => tracing::warn!("skipping bad line in {}: {err}", path.display()),
+        }
+    }
+    Ok(points)
+}
+
+/// Writes to a temporary file first, so a crash can't lose the day.
+fn write_points(path: &Path, points: &[Point]) -> Result<()> {
+    let temp_path = path.with_extension("jsonl.tmp");
+    let mut data = vec![];
+    for point in points {
+        serde_json::to_writer(&mut data, point)?;
+        data.push(b'\n');
+    }
+    fs::write(&temp_path, data)?;
+    fs::rename(temp_path, path)?;
+    Ok(())
+}
+
+fn uptime_percent(point: &Point) -> Option<f64> {
+    (point.samples > 0).then(|| point.online_samples as f64 * 100.0 / point.samples as f64)
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryReport {
+    pub from: u64,
+    pub to: u64,
+    pub resolution: u64,
+    /// `None` when nothing was recorded in the range.
+    pub uptime_percent: Option<f64>,
+    pub series: Vec<HistoryPoint>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryPoint {
+    pub time: u64,
+    pub uptime_percent: Option<f64>,
+    pub players_avg: Option<f64>,
+    pub players_peak: Option<i32>,
+    pub latency_avg_ms: Option<f64>,
+}
+
+impl From<&Point> for HistoryPoint {
+    fn from(point: &Point) -> Self {
+        let has_players = point.player_samples > 0;
+        Self {
+            time: point.time,
+            uptime_percent: uptime_percent(point),
+            players_avg: has_players
+                .then(|| point.players_sum as f64 / point.player_samples as f64),
+            players_peak: has_players.then_some(point.players_peak),
+            latency_avg_ms: (point.latency_samples > 0)
+                .then(|| point.latency_sum_ms / point.latency_samples as f64),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryQuery {
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+    pub resolution: Option<u64>,
+}
+
+/// Defaults to the last day at the downsampled resolution.
+pub async fn history_route(
+    history: Option<History>,
+    query: HistoryQuery,
+) -> Response<HistoryReport> {
+    super::handle_route(move || {
+        let history = history.ok_or_else(not_enabled)?;
+        let to = query.to.unwrap_or_else(now);
+        let from = query.from.unwrap_or(to.saturating_sub(DAY));
+        let resolution = query.resolution.unwrap_or(DOWNSAMPLED_RESOLUTION);
+        history.report(from, to, resolution)
+    })
+    .await
+}
+
+fn not_enabled() -> Error {
+    Error::Generic("history is not enabled".to_owned())
+}
+
+#[derive(Debug, Serialize)]
+pub struct DailyPeak {
+    /// Start of the day, unix seconds.
+    pub day: u64,
+    pub peak: Option<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DailyPeaksQuery {
+    /// How many days back to look, a week by default.
+    pub days: Option<u64>,
+}
+
+/// Peak concurrent players of each day, from the player counts of status
+/// polls.
+pub async fn daily_peaks_route(
+    history: Option<History>,
+    query: DailyPeaksQuery,
+) -> Response<Vec<DailyPeak>> {
+    super::handle_route(move || {
+        let history = history.ok_or_else(not_enabled)?;
+        let to = now();
+        let from = (to / DAY).saturating_sub(query.days.unwrap_or(7).saturating_sub(1)) * DAY;
+        let report = history.report(from, to, DAY)?;
+        Ok(report
+            .series
+            .into_iter()
+            .map(|point| DailyPeak {
+                day: point.time,
+                peak: point.players_peak,
+            })
+            .collect())
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open_history(name: &str, config: HistoryConfig) -> History {
+        let dir = std::env::temp_dir().join(format!("mc-history-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        History::open(dir, config).unwrap()
+    }
+
+    fn online(players: i32) -> Sample {
+        Sample {
+            online: true,
+            players_online: Some(players),
+            latency_ms: Some(10.0),
+        }
+    }
+
+    #[test]
+    fn report_buckets() {
+        let history = open_history("report", HistoryConfig::default());
+        let start = 100 * DAY;
+        history.record_at(start, online(2)).unwrap();
+        history.record_at(start + 30, online(4)).unwrap();
+        history.record_at(start + 60, Sample::default()).unwrap();
+        history.record_at(start + 90, online(1)).unwrap();
+
+        let report = history.report(start, start + 120, 60).unwrap();
+        assert_eq!(report.uptime_percent, Some(75.0));
+        assert_eq!(report.series.len(), 2);
+        assert_eq!(report.series[0].time, start);
+        assert_eq!(report.series[0].players_avg, Some(3.0));
+        assert_eq!(report.series[0].players_peak, Some(4));
+        assert_eq!(report.series[1].uptime_percent, Some(50.0));
+        assert_eq!(report.series[1].players_avg, Some(1.0));
+
+        let empty = history.report(start + DAY, start + 2 * DAY, 60).unwrap();
+        assert_eq!(empty.uptime_percent, None);
+        assert!(empty.series.is_empty());
+        assert!(history.report(start, start, 60).is_err());
+    }
+
+    #[test]
+    fn report_reads_only_recorded_days() {
+        let history = open_history("unbounded", HistoryConfig::default());
+        history.record_at(100 * DAY, online(3)).unwrap();
+
+        let report = history.report(0, u64::MAX, 60).unwrap();
+        assert_eq!(report.uptime_percent, Some(100.0));
+        assert_eq!(report.series[0].players_peak, Some(3));
+    }
+
+    #[test]
+    fn retention_and_downsampling() {
+        let config = HistoryConfig {
+            retention_days: 3,
+            raw_days: 1,
+        };
+        let history = open_history("retention", config);
+        for day in [100, 102] {
+            for minute in 0..10 {
+                history
+                    .record_at(day * DAY + minute * 60, online(1))
+                    .unwrap();
+            }
+        }
+
+        // a new day triggers maintenance
+        history.record_at(104 * DAY, online(1)).unwrap();
+        let inner = history.inner.lock().unwrap();
+        assert!(!inner.day_path(100).exists());
+        let downsampled = read_points(&inner.day_path(102)).unwrap();
+        assert_eq!(downsampled.len(), 2);
+        assert_eq!(downsampled[0].samples, 5);
+        assert_eq!(read_points(&inner.day_path(104)).unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,22 @@
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+/// Round-trip times in milliseconds of the stages of a single exchange with
+/// the server. `handshake` covers the TCP connect and sending the handshake,
+/// stages that weren't part of the exchange are left out.
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Latency {
+    pub handshake_ms: Option<f64>,
+    pub status_ms: Option<f64>,
+    pub ping_ms: Option<f64>,
+}
+
+pub fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+pub fn millis_since(instant: Instant) -> f64 {
+    millis(instant.elapsed())
+}
@@ -0,0 +1,191 @@
+//! Server List Ping used by servers older than 1.7, which don't understand the
+//! handshake/status protocol. See <https://wiki.vg/Server_List_Ping#1.6>.
+
+use std::{
+    io::{Read, Write},
+    net::{SocketAddr, TcpStream},
+};
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::error::Error;
+
+use super::{
+    latency::Latency,
+    packet::status::{Description, Players, ServerType, StatusResponse, Version},
+    Result,
+};
+
+const SERVER_LIST_PING: u8 = 0xFE;
+const SERVER_LIST_PING_PAYLOAD: u8 = 0x01;
+const PLUGIN_MESSAGE: u8 = 0xFA;
+const KICK: u8 = 0xFF;
+const PING_HOST_CHANNEL: &str = "MC|PingHost";
+const PING_HOST_PROTOCOL: u8 = 74;
+
+/// Sends the 1.6 style `0xFE 0x01` ping. 1.4 and 1.5 servers ignore the
+/// trailing plugin message and beta servers ignore everything after `0xFE`,
+/// so the response can be in either format. `addr` is the address the server
+/// is reached at, sent along like the handshake of newer clients does.
+pub fn status(stream: &mut TcpStream, addr: SocketAddr) -> Result<StatusResponse> {
+    let mut ping_host = vec![PING_HOST_PROTOCOL];
+    write_utf16_string(&mut ping_host, &addr.ip().to_string())?;
+    ping_host.write_i32::<BigEndian>(addr.port().into())?;
+
+    let mut request = vec![SERVER_LIST_PING, SERVER_LIST_PING_PAYLOAD, PLUGIN_MESSAGE];
+    write_utf16_string(&mut request, PING_HOST_CHANNEL)?;
+    request.write_u16::<BigEndian>(ping_host.len() as u16)?;
+    request.extend(ping_host);
+    stream.write_all(&request)?;
+
+    recv_status(stream)
+}
+
+/// Sends the bare `0xFE` ping understood by beta 1.8 - 1.3 servers.
+pub fn beta_status(stream: &mut TcpStream) -> Result<StatusResponse> {
+    stream.write_all(&[SERVER_LIST_PING])?;
+
+    recv_status(stream)
+}
+
+fn recv_status(stream: &mut TcpStream) -> Result<StatusResponse> {
+    if stream.read_u8()? != KICK {
+        return Err(Error::Generic("wrong legacy packet id".to_owned()));
+    }
+
+    let length = stream.read_u16::<BigEndian>()?;
+    let mut buffer = vec![0u8; 2 * length as usize];
+    stream.read_exact(&mut buffer)?;
+    let payload = char::decode_utf16(
+        buffer
+            .chunks_exact(2)
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]])),
+    )
+    .collect::<std::result::Result<String, _>>()
+    .map_err(|err| Error::Generic(format!("invalid legacy ping payload: {err}")))?;
+
+    parse_payload(&payload)
+}
+
+fn parse_payload(payload: &str) -> Result<StatusResponse> {
+    let invalid = || Error::Generic(format!("invalid legacy ping payload `{payload}`"));
+
+    let (protocol, name, motd, online, max) = match payload.strip_prefix("§1\0") {
+        // 1.4+: §1\0<protocol>\0<version>\0<motd>\0<online>\0<max>
+        Some(rest) => {
+            let fields: Vec<&str> = rest.split('\0').collect();
+            let [protocol, name, motd, online, max] = fields[..] else {
+                return Err(invalid());
+            };
+            (protocol.parse()?, name.to_owned(), motd, online, max)
+        }
+        // beta: <motd>§<online>§<max>, the motd itself may contain `§`
+        None => {
+            let mut fields = payload.rsplitn(3, '§');
+            let max = fields.next().ok_or_else(invalid)?;
+            let online = fields.next().ok_or_else(invalid)?;
+            let motd = fields.next().ok_or_else(invalid)?;
+            (-1, String::new(), motd, online, max)
+        }
+    };
+
+    Ok(StatusResponse {
+        server_type: ServerType::Vanilla,
+        previews_chat: false,
+        enforces_secure_chat: false,
+        description: Description {
+            text: motd.to_owned(),
+        },
+        players: Players {
+            max: max.parse()?,
+            online: online.parse()?,
+            sample: vec![],
+        },
+        version: Version { name, protocol },
+        favicon: None,
+        forge_data: None,
+        prevents_chat_reports: false,
+        latency: Latency::default(),
+    })
+}
+
+fn write_utf16_string(buffer: &mut Vec<u8>, string: &str) -> Result<()> {
+    let units: Vec<u16> = string.encode_utf16().collect();
+    buffer.write_u16::<BigEndian>(units.len() as u16)?;
+    for unit in units {
+        buffer.write_u16::<BigEndian>(unit)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{Ipv4Addr, TcpListener},
+        thread,
+    };
+
+    use super::*;
+
+    #[test]
+    fn one_four_payload() {
+        let status = parse_payload("§1\x0078\x001.6.4\x00A Minecraft Server\x003\x0020").unwrap();
+        assert_eq!(status.version.protocol, 78);
+        assert_eq!(status.version.name, "1.6.4");
+        assert_eq!(status.description.text, "A Minecraft Server");
+        assert_eq!(status.players.online, 3);
+        assert_eq!(status.players.max, 20);
+    }
+
+    #[test]
+    fn beta_payload() {
+        let status = parse_payload("A §4red§r server§3§20").unwrap();
+        assert_eq!(status.version.protocol, -1);
+        assert_eq!(status.version.name, "");
+        assert_eq!(status.description.text, "A §4red§r server");
+        assert_eq!(status.players.online, 3);
+        assert_eq!(status.players.max, 20);
+    }
+
+    #[test]
+    fn invalid_payloads() {
+        assert!(parse_payload("§1\x0078\x001.6.4\x00motd\x003").is_err());
+        assert!(parse_payload("motd§3").is_err());
+        assert!(parse_payload("motd§three§20").is_err());
+    }
+
+    #[test]
+    fn ping_host_carries_address() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut header = [0u8; 3];
+            stream.read_exact(&mut header).unwrap();
+            assert_eq!(
+                header,
+                [SERVER_LIST_PING, SERVER_LIST_PING_PAYLOAD, PLUGIN_MESSAGE]
+            );
+            let channel_len = stream.read_u16::<BigEndian>().unwrap();
+            let mut channel = vec![0u8; 2 * channel_len as usize];
+            stream.read_exact(&mut channel).unwrap();
+            let ping_host_len = stream.read_u16::<BigEndian>().unwrap();
+            let mut ping_host = vec![0u8; ping_host_len as usize];
+            stream.read_exact(&mut ping_host).unwrap();
+
+            let mut reply = vec![KICK];
+            write_utf16_string(&mut reply, "§1\x0078\x001.6.4\x00motd\x000\x0020").unwrap();
+            stream.write_all(&reply).unwrap();
+            ping_host
+        });
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        status(&mut stream, addr).unwrap();
+
+        let mut expected = vec![PING_HOST_PROTOCOL];
+        write_utf16_string(&mut expected, "127.0.0.1").unwrap();
+        expected.extend(i32::from(addr.port()).to_be_bytes());
+        assert_eq!(server.join().unwrap(), expected);
+    }
+}
@@ -1,70 +1,238 @@
 use std::{
     net::{SocketAddr, TcpStream},
     result,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use crate::{error::Error, response::Response};
 
+use self::bedrock::BedrockStatus;
+use self::latency::{millis, millis_since, Latency};
+use self::modpack::ModpackDiff;
 use self::packet::{
-    handshake::Handshake,
+    handshake::{Handshake, AUTO_PROTOCOL_VERSION},
     ping::{PingRequest, PingResponse},
     status::{StatusRequest, StatusResponse},
     Packet, SendPacket,
 };
-
-mod bytes;
+use self::query::{full_stat, FullStat};
+
+pub mod bedrock;
+pub mod bytes;
+pub mod favicon;
+pub mod history;
+pub mod latency;
+mod legacy;
+pub mod modpack;
 pub mod packet;
+pub mod query;
+pub mod rcon;
+pub mod sessions;
+pub mod varint;
 
 type Result<T> = result::Result<T, Error>;
 
+/// Which Server List Ping flavour to use, set through `MC_PING_PROTOCOL`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PingProtocol {
+    /// Modern ping, falling back to the legacy one when it fails.
+    Auto,
+    /// Handshake/status protocol used since 1.7.
+    Modern,
+    /// `0xFE 0x01` ping used by 1.4 - 1.6.
+    Legacy,
+    /// `0xFE` ping used by beta 1.8 - 1.3.
+    Beta,
+}
+
+impl PingProtocol {
+    fn from_env() -> Self {
+        match std::env::var("MC_PING_PROTOCOL").as_deref() {
+            Ok("modern") => PingProtocol::Modern,
+            Ok("legacy") => PingProtocol::Legacy,
+            Ok("beta") => PingProtocol::Beta,
+            _ => PingProtocol::Auto,
+        }
+    }
+}
+
+/// Protocol sent in the handshake, set through `MC_PROTOCOL_VERSION`.
+fn protocol_version() -> i32 {
+    std::env::var("MC_PROTOCOL_VERSION")
+        .ok()
+        .and_then(|protocol| protocol.parse().ok())
+        .unwrap_or(AUTO_PROTOCOL_VERSION)
+}
+
+/// Protocol of the client players are expected to use, set through
+/// `MC_EXPECTED_PROTOCOL_VERSION`.
+pub fn expected_protocol_version() -> Option<i32> {
+    std::env::var("MC_EXPECTED_PROTOCOL_VERSION")
+        .ok()
+        .and_then(|protocol| protocol.parse().ok())
+}
+
+fn handshake() -> Handshake {
+    Handshake {
+        protocol_version: protocol_version(),
+        ..Default::default()
+    }
+}
+
+/// Address of the server to report on, set through `MC_SERVER_ADDR` and
+/// `MC_SERVER_PORT`.
+pub fn server_addr() -> Result<SocketAddr> {
+    let ip = std::env::var("MC_SERVER_ADDR")?.parse()?;
+    let port = std::env::var("MC_SERVER_PORT")?.parse()?;
+
+    Ok(SocketAddr::new(ip, port))
+}
+
 pub fn status() -> Result<StatusResponse> {
-    let mut stream = create_tcp_stream()?;
+    status_at(server_addr()?)
+}
+
+pub fn status_at(addr: SocketAddr) -> Result<StatusResponse> {
+    match PingProtocol::from_env() {
+        PingProtocol::Modern => modern_status(addr),
+        PingProtocol::Legacy => legacy_status(addr, |stream| legacy::status(stream, addr)),
+        PingProtocol::Beta => legacy_status(addr, legacy::beta_status),
+        PingProtocol::Auto => modern_status(addr).or_else(|err| {
+            tracing::debug!("modern status failed with `{err}`, trying legacy ping");
+            any_legacy_status(addr).map_err(|_| err)
+        }),
+    }
+}
+
+/// Tries the 1.6 ping first and the bare beta one when the server doesn't
+/// answer it.
+fn any_legacy_status(addr: SocketAddr) -> Result<StatusResponse> {
+    legacy_status(addr, |stream| legacy::status(stream, addr)).or_else(|err| {
+        tracing::debug!("legacy ping failed with `{err}`, trying beta ping");
+        legacy_status(addr, legacy::beta_status)
+    })
+}
+
+fn modern_status(addr: SocketAddr) -> Result<StatusResponse> {
+    let start = Instant::now();
+    let mut stream = create_tcp_stream(addr)?;
     let stream = &mut stream;
 
-    Handshake::default().send_packet(stream)?;
+    handshake().send_packet(stream)?;
+    let handshake_done = Instant::now();
 
     StatusRequest.send_packet(stream)?;
 
-    Packet::recv(stream)?.try_into()
+    let mut status: StatusResponse = Packet::recv(stream)?.try_into()?;
+    status.latency = Latency {
+        handshake_ms: Some(millis(handshake_done - start)),
+        status_ms: Some(millis_since(handshake_done)),
+        ping_ms: None,
+    };
+    Ok(status)
+}
+
+fn legacy_status(
+    addr: SocketAddr,
+    send_status: impl FnOnce(&mut TcpStream) -> Result<StatusResponse>,
+) -> Result<StatusResponse> {
+    let start = Instant::now();
+    let mut stream = create_tcp_stream(addr)?;
+    let connected = Instant::now();
+
+    let mut status = send_status(&mut stream)?;
+    status.latency = Latency {
+        handshake_ms: Some(millis(connected - start)),
+        status_ms: Some(millis_since(connected)),
+        ping_ms: None,
+    };
+    Ok(status)
 }
 
 pub fn ping(payload: i64) -> Result<PingResponse> {
-    let mut stream = create_tcp_stream()?;
+    ping_at(server_addr()?, payload)
+}
+
+/// Legacy servers have no ping packet, so a successful legacy status stands in
+/// for the pong and the payload is echoed back as is.
+pub fn ping_at(addr: SocketAddr, payload: i64) -> Result<PingResponse> {
+    let legacy_ping = |status: StatusResponse| PingResponse {
+        payload,
+        latency: Latency {
+            ping_ms: status.latency.status_ms,
+            ..status.latency
+        },
+    };
+
+    match PingProtocol::from_env() {
+        PingProtocol::Modern => modern_ping(addr, payload),
+        PingProtocol::Legacy => {
+            legacy_status(addr, |stream| legacy::status(stream, addr)).map(legacy_ping)
+        }
+        PingProtocol::Beta => legacy_status(addr, legacy::beta_status).map(legacy_ping),
+        PingProtocol::Auto => modern_ping(addr, payload)
+            .or_else(|err| any_legacy_status(addr).map(legacy_ping).map_err(|_| err)),
+    }
+}
+
+fn modern_ping(addr: SocketAddr, payload: i64) -> Result<PingResponse> {
+    let start = Instant::now();
+    let mut stream = create_tcp_stream(addr)?;
     let stream = &mut stream;
 
-    Handshake::default().send_packet(stream)?;
+    handshake().send_packet(stream)?;
+    let handshake_done = Instant::now();
 
     PingRequest { payload }.send_packet(stream)?;
 
-    Packet::recv(stream)?.try_into()
+    let mut ping: PingResponse = Packet::recv(stream)?.try_into()?;
+    ping.latency = Latency {
+        handshake_ms: Some(millis(handshake_done - start)),
+        status_ms: None,
+        ping_ms: Some(millis_since(handshake_done)),
+    };
+    Ok(ping)
 }
 
-fn create_tcp_stream() -> Result<TcpStream> {
-    let ip = std::env::var("MC_SERVER_ADDR")?.parse()?;
-    let port = std::env::var("MC_SERVER_PORT")?.parse()?;
-
+fn create_tcp_stream(addr: SocketAddr) -> Result<TcpStream> {
     let five_sec = Duration::from_secs(5);
-    let stream = TcpStream::connect_timeout(&SocketAddr::new(ip, port), five_sec)?;
+    let stream = TcpStream::connect_timeout(&addr, five_sec)?;
     stream.set_read_timeout(Some(five_sec))?;
     stream.set_write_timeout(Some(five_sec))?;
 
     Ok(stream)
 }
-fn handle_route<F, T>(route_func: F) -> Response<T>
+
+/// Runs the route on the blocking pool, a server that doesn't answer can hold
+/// it up for the whole connect and read timeouts of every ping it tries.
+async fn handle_route<F, T>(route_func: F) -> Response<T>
 where
-    F: FnOnce() -> Result<T>,
+    F: FnOnce() -> Result<T> + Send + 'static,
+    T: Send + 'static,
 {
-    match route_func() {
-        Ok(val) => Response::ok(val),
-        Err(err) => Response::err(err),
+    match tokio::task::spawn_blocking(route_func).await {
+        Ok(Ok(val)) => Response::ok(val),
+        Ok(Err(err)) => Response::err(err),
+        Err(err) => Response::err(Error::Generic(format!("route task failed: {err}"))),
     }
 }
 
-pub fn ping_route(payload: i64) -> Response<PingResponse> {
-    handle_route(|| ping(payload))
+pub async fn ping_route(payload: i64) -> Response<PingResponse> {
+    handle_route(move || ping(payload)).await
+}
+
+pub async fn status_route() -> Response<StatusResponse> {
+    handle_route(status).await
+}
+
+pub async fn query_route() -> Response<FullStat> {
+    handle_route(|| full_stat(query::query_addr()?)).await
+}
+
+pub async fn bedrock_route() -> Response<BedrockStatus> {
+    handle_route(bedrock::status).await
 }
 
-pub fn status_route() -> Response<StatusResponse> {
-    handle_route(status)
+pub async fn modpack_route() -> Response<ModpackDiff> {
+    handle_route(modpack::diff_with_server).await
 }
@@ -0,0 +1,380 @@
+//! Compares the mods a Forge server reports against the modpack players are
+//! supposed to install.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+
+use super::{packet::status::ForgeMod, Result};
+
+/// Mods every Forge server reports, they come with the loader and never show
+/// up in a modpack manifest.
+const LOADER_MOD_IDS: &[&str] = &["minecraft", "forge", "neoforge", "fml", "mcp"];
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExpectedMod {
+    pub id: String,
+    /// Any version is accepted when `None`.
+    pub version: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModpackDiff {
+    /// In the modpack but not on the server.
+    pub missing: Vec<ExpectedMod>,
+    /// On the server but not in the modpack.
+    pub extra: Vec<ForgeMod>,
+    pub mismatched: Vec<VersionMismatch>,
+    /// The server left mods out of its list, so `missing` may list mods the
+    /// server actually has.
+    pub truncated: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct VersionMismatch {
+    pub id: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+/// Mods listed under `mods` or `files`. Our own entries name the mod id, the
+/// entries of CurseForge (`projectID`/`fileID`) and Modrinth (`path`/`hashes`)
+/// manifests are resolved to one, see [`UnparsedManifestMod::mod_id`].
+#[derive(Deserialize)]
+struct UnparsedManifest {
+    #[serde(alias = "files")]
+    mods: Vec<UnparsedManifestMod>,
+}
+
+#[derive(Deserialize)]
+struct UnparsedManifestMod {
+    #[serde(alias = "modId", alias = "slug")]
+    id: Option<String>,
+    #[serde(alias = "modVersion")]
+    version: Option<String>,
+    #[serde(default = "required_default")]
+    required: bool,
+    #[serde(rename = "projectID")]
+    project_id: Option<u64>,
+    path: Option<String>,
+    env: Option<ModrinthEnv>,
+}
+
+/// Where a Modrinth file is needed, `unsupported` on the server for client
+/// only mods.
+#[derive(Deserialize)]
+struct ModrinthEnv {
+    server: String,
+}
+
+fn required_default() -> bool {
+    true
+}
+
+impl UnparsedManifestMod {
+    fn on_server(&self) -> bool {
+        self.required
+            && self
+                .env
+                .as_ref()
+                .is_none_or(|env| env.server != "unsupported")
+    }
+
+    /// The entry's own id, else the configured id for its CurseForge project
+    /// or Modrinth file name, else the id guessed from the file name.
+    fn mod_id(&self, mod_ids: &HashMap<String, String>) -> Option<String> {
+        if let Some(id) = &self.id {
+            return Some(id.clone());
+        }
+        if let Some(project_id) = self.project_id {
+            return mod_ids.get(&project_id.to_string()).cloned();
+        }
+
+        let file_name = self.path.as_deref()?.rsplit('/').next()?;
+        mod_ids
+            .get(file_name)
+            .cloned()
+            .or_else(|| mod_id_from_file_name(file_name))
+    }
+
+    fn describe(&self) -> String {
+        match (self.project_id, &self.path) {
+            (Some(project_id), _) => format!("CurseForge project {project_id}"),
+            (None, Some(path)) => format!("file `{path}`"),
+            (None, None) => "entry without an id".to_owned(),
+        }
+    }
+}
+
+/// Jars are usually named `<mod id>-<minecraft version>-<mod version>.jar`,
+/// so the id is everything before the first version or loader part.
+fn mod_id_from_file_name(file_name: &str) -> Option<String> {
+    let is_id_part = |part: &&str| {
+        let part = part.to_lowercase();
+        let version = part.strip_prefix("mc").unwrap_or(&part);
+        !version.starts_with(|c: char| c.is_ascii_digit())
+            && !["forge", "neoforge", "fabric"].contains(&part.as_str())
+    };
+    let id = file_name
+        .strip_suffix(".jar")?
+        .split(['-', '_', '+'])
+        .take_while(is_id_part)
+        .collect::<Vec<_>>()
+        .join("_")
+        .to_lowercase();
+    (!id.is_empty()).then_some(id)
+}
+
+/// Entries that can't be resolved to a mod id are skipped with a warning, the
+/// rest of the modpack is still compared.
+fn parse_manifest(manifest: &str, mod_ids: &HashMap<String, String>) -> Result<Vec<ExpectedMod>> {
+    let manifest: UnparsedManifest = serde_json::from_str(manifest)?;
+    Ok(manifest
+        .mods
+        .into_iter()
+        .filter(UnparsedManifestMod::on_server)
+        .filter_map(|m| {
+            let Some(id) = m.mod_id(mod_ids) else {
+                tracing::warn!(
+                    "skipping {} in the modpack manifest, add its mod id to MC_MODPACK_MOD_IDS",
+                    m.describe()
+                );
+                return None;
+            };
+            Some(ExpectedMod {
+                id,
+                version: m.version.filter(|version| !version.is_empty()),
+            })
+        })
+        .collect())
+}
+
+/// Parses `id=version` pairs separated by commas, the version is optional.
+fn parse_mods_list(mods: &str) -> Vec<ExpectedMod> {
+    mods.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| match entry.split_once('=') {
+            Some((id, version)) => ExpectedMod {
+                id: id.trim().to_owned(),
+                version: Some(version.trim().to_owned()).filter(|version| !version.is_empty()),
+            },
+            None => ExpectedMod {
+                id: entry.to_owned(),
+                version: None,
+            },
+        })
+        .collect()
+}
+
+/// Parses `key=mod id` pairs separated by commas, keyed by CurseForge project
+/// id or Modrinth file name.
+fn parse_mod_ids(mod_ids: &str) -> HashMap<String, String> {
+    mod_ids
+        .split(',')
+        .filter_map(|entry| entry.split_once('='))
+        .map(|(key, id)| (key.trim().to_owned(), id.trim().to_owned()))
+        .filter(|(key, id)| !key.is_empty() && !id.is_empty())
+        .collect()
+}
+
+/// Mods of the modpack, read from the JSON manifest at `MC_MODPACK_MANIFEST`
+/// or the `MC_MODPACK_MODS` list, `None` when neither is set.
+/// `MC_MODPACK_MOD_IDS` names the mod ids of manifest entries that don't carry
+/// one.
+pub fn expected_mods() -> Result<Option<Vec<ExpectedMod>>> {
+    if let Ok(path) = std::env::var("MC_MODPACK_MANIFEST") {
+        let mod_ids = std::env::var("MC_MODPACK_MOD_IDS")
+            .map(|mod_ids| parse_mod_ids(&mod_ids))
+            .unwrap_or_default();
+        return parse_manifest(&std::fs::read_to_string(path)?, &mod_ids).map(Some);
+    }
+    Ok(std::env::var("MC_MODPACK_MODS")
+        .ok()
+        .map(|mods| parse_mods_list(&mods)))
+}
+
+pub fn diff(expected: &[ExpectedMod], mods: &[ForgeMod], truncated: bool) -> ModpackDiff {
+    let server_mods: HashMap<_, _> = mods.iter().map(|m| (m.id.as_str(), m)).collect();
+
+    let mut missing = vec![];
+    let mut mismatched = vec![];
+    for expected_mod in expected {
+        let Some(server_mod) = server_mods.get(expected_mod.id.as_str()) else {
+            missing.push(expected_mod.clone());
+            continue;
+        };
+        if server_mod.ignore_server_only {
+            continue;
+        }
+        if let (Some(expected_version), Some(actual)) = (&expected_mod.version, &server_mod.version)
+        {
+            if expected_version != actual {
+                mismatched.push(VersionMismatch {
+                    id: expected_mod.id.clone(),
+                    expected: expected_version.clone(),
+                    actual: actual.clone(),
+                });
+            }
+        }
+    }
+
+    let extra = mods
+        .iter()
+        .filter(|m| !LOADER_MOD_IDS.contains(&m.id.to_lowercase().as_str()))
+        .filter(|m| !expected.iter().any(|expected_mod| expected_mod.id == m.id))
+        .cloned()
+        .collect();
+
+    ModpackDiff {
+        missing,
+        extra,
+        mismatched,
+        truncated,
+    }
+}
+
+pub fn diff_with_server() -> Result<ModpackDiff> {
+    let expected = expected_mods()?
+        .ok_or_else(|| Error::Generic("no modpack manifest configured".to_owned()))?;
+    let forge_data = super::status()?
+        .forge_data
+        .ok_or_else(|| Error::Generic("server did not report any mods".to_owned()))?;
+
+    Ok(diff(&expected, &forge_data.d.mods, forge_data.d.truncated))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server_mod(id: &str, version: Option<&str>) -> ForgeMod {
+        ForgeMod {
+            id: id.to_owned(),
+            version: version.map(str::to_owned),
+            ignore_server_only: version.is_none(),
+            channels: vec![],
+            required_on_client: version.is_some(),
+        }
+    }
+
+    #[test]
+    fn manifest_formats() {
+        let manifest = r#"{
+            "mods": [
+                {"id": "jei", "version": "15.2.0.27"},
+                {"modId": "spark", "version": ""},
+                {"id": "optional", "required": false}
+            ]
+        }"#;
+        assert_eq!(
+            parse_manifest(manifest, &HashMap::new()).unwrap(),
+            vec![
+                ExpectedMod {
+                    id: "jei".to_owned(),
+                    version: Some("15.2.0.27".to_owned()),
+                },
+                ExpectedMod {
+                    id: "spark".to_owned(),
+                    version: None,
+                },
+            ]
+        );
+
+        assert_eq!(
+            parse_mods_list("jei=15.2.0.27, spark,"),
+            vec![
+                ExpectedMod {
+                    id: "jei".to_owned(),
+                    version: Some("15.2.0.27".to_owned()),
+                },
+                ExpectedMod {
+                    id: "spark".to_owned(),
+                    version: None,
+                },
+            ]
+        );
+    }
+
+    fn ids(mods: Vec<ExpectedMod>) -> Vec<String> {
+        mods.into_iter().map(|m| m.id).collect()
+    }
+
+    #[test]
+    fn curseforge_manifest() {
+        let manifest = include_str!("../../../tests/fixtures/curseforge_manifest.json");
+
+        // projects without a configured mod id are skipped
+        let mod_ids = parse_mod_ids("238222=jei, 328085=create");
+        assert_eq!(
+            ids(parse_manifest(manifest, &mod_ids).unwrap()),
+            ["jei", "create"]
+        );
+        assert_eq!(parse_manifest(manifest, &HashMap::new()).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn modrinth_manifest() {
+        let manifest = include_str!("../../../tests/fixtures/modrinth.index.json");
+
+        // the client only mod is left out
+        let mod_ids = parse_mod_ids("Xaeros_Minimap_23.8.4_Forge_1.20.jar=xaerominimap");
+        assert_eq!(
+            ids(parse_manifest(manifest, &mod_ids).unwrap()),
+            ["jei", "create", "journeymap", "xaerominimap"]
+        );
+    }
+
+    #[test]
+    fn mod_ids_from_file_names() {
+        let id = |file_name| mod_id_from_file_name(file_name);
+        assert_eq!(id("jei-1.20.1-forge-15.2.0.27.jar").unwrap(), "jei");
+        assert_eq!(id("create-1.20.1-0.5.1.f.jar").unwrap(), "create");
+        assert_eq!(
+            id("journeymap-1.20.1-5.9.7-forge.jar").unwrap(),
+            "journeymap"
+        );
+        assert_eq!(id("spark-1.10.53-forge.jar").unwrap(), "spark");
+        assert_eq!(id("Clumps-forge-1.20.1-12.0.0.3.jar").unwrap(), "clumps");
+        assert_eq!(
+            id("ftbultimine-mc1.20.1-2001.1.4.jar").unwrap(),
+            "ftbultimine"
+        );
+        assert_eq!(id("1.20.1-mod.jar"), None);
+        assert_eq!(id("readme.txt"), None);
+    }
+
+    #[test]
+    fn diff_mods() {
+        let expected = parse_mods_list("jei=15.2.0.27,create=0.5.1,spark=1.10.53,journeymap");
+        let mods = [
+            server_mod("minecraft", Some("1.20.1")),
+            server_mod("forge", Some("47.2.0")),
+            server_mod("jei", Some("15.2.0.26")),
+            server_mod("spark", None),
+            server_mod("journeymap", Some("5.9.7")),
+            server_mod("ftbchunks", Some("2001.2.3")),
+        ];
+
+        let diff = diff(&expected, &mods, false);
+        assert_eq!(
+            diff.missing,
+            vec![ExpectedMod {
+                id: "create".to_owned(),
+                version: Some("0.5.1".to_owned()),
+            }]
+        );
+        assert_eq!(diff.extra, vec![mods[5].clone()]);
+        assert_eq!(
+            diff.mismatched,
+            vec![VersionMismatch {
+                id: "jei".to_owned(),
+                expected: "15.2.0.27".to_owned(),
+                actual: "15.2.0.26".to_owned(),
+            }]
+        );
+    }
+}
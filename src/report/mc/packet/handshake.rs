@@ -1,16 +1,20 @@
 use bytes::BufMut;
 
-use crate::report::mc::bytes::{Bytes, Varint};
+use crate::report::mc::bytes::Bytes;
 
 use super::{Packet, SendPacket};
 
 pub struct Handshake {
-    pub protocol_version: Varint,
+    pub protocol_version: i32,
     pub server_address: String,
     pub server_port: u16,
     pub next_state: NextState,
 }
 
+/// Sent when the client doesn't know which version the server runs, the server
+/// then answers with its own protocol in the status response.
+pub const AUTO_PROTOCOL_VERSION: i32 = -1;
+
 pub enum NextState {
     Status,
     _Login,
@@ -19,7 +23,7 @@ pub enum NextState {
 impl Default for Handshake {
     fn default() -> Self {
         Self {
-            protocol_version: 760,
+            protocol_version: AUTO_PROTOCOL_VERSION,
             server_address: "127.0.0.1".to_string(),
             server_port: 25565,
             next_state: NextState::Status,
@@ -2,32 +2,28 @@ pub mod handshake;
 pub mod ping;
 pub mod status;
 
-use std::{
-    io::{self, Read, Write},
-    net::TcpStream,
+use std::io::{self, Read, Write};
+
+use crate::error::Error;
+
+use byteorder::ReadBytesExt;
+
+use super::{
+    bytes::{Bytes, DecodeError},
+    varint::VarInt,
 };
 
-use super::bytes::{Bytes, Varint};
+/// Largest packet a vanilla server accepts, 3 byte varint length.
+const MAX_PACKET_LENGTH: i32 = 2_097_151;
 
 #[derive(Debug)]
 pub struct Packet {
-    pub id: Varint,
+    pub id: i32,
     pub data: Bytes,
 }
 
-fn leb128_err_to_ioerr(err: leb128::read::Error) -> std::io::Error {
-    match err {
-        leb128::read::Error::IoError(err) => err,
-        leb128::read::Error::Overflow => std::io::Error::new(std::io::ErrorKind::Other, "overflow"),
-    }
-}
-
-fn try_from_int_to_ioerr(_err: std::num::TryFromIntError) -> std::io::Error {
-    std::io::Error::new(std::io::ErrorKind::Other, "overflow")
-}
-
 pub trait SendPacket {
-    fn send_packet(self, stream: &mut TcpStream) -> io::Result<usize>
+    fn send_packet(self, stream: &mut impl Write) -> io::Result<usize>
     where
         Self: Sized + Into<Packet>,
     {
@@ -36,18 +32,22 @@ pub trait SendPacket {
 }
 
 impl Packet {
-    pub fn send(self, stream: &mut TcpStream) -> io::Result<usize> {
+    pub fn send(self, stream: &mut impl Write) -> io::Result<usize> {
         let data: Vec<u8> = self.into();
-        stream.write(&data)
+        stream.write_all(&data)?;
+        Ok(data.len())
     }
 
-    pub fn recv(stream: &mut TcpStream) -> io::Result<Packet> {
-        let length = leb128::read::signed(stream).map_err(leb128_err_to_ioerr)?;
-        let mut buffer = vec![0u8; length.try_into().map_err(try_from_int_to_ioerr)?];
+    pub fn recv(stream: &mut impl Read) -> Result<Packet, Error> {
+        let VarInt(length) = VarInt::decode(|| Ok::<_, Error>(stream.read_u8()?))?;
+        if !(1..=MAX_PACKET_LENGTH).contains(&length) {
+            return Err(DecodeError::InvalidLength(length.into()).into());
+        }
+        let mut buffer = vec![0u8; length as usize];
         stream.read_exact(&mut buffer)?;
         let mut data: Bytes = buffer.into();
 
-        let id = data.get_varint();
+        let id = data.get_varint()?;
         Ok(Packet { id, data })
     }
 }
@@ -55,7 +55,8 @@ impl Packet {
 impl From<Packet> for Vec<u8> {
     fn from(packet: Packet) -> Self {
         let mut bytes: Bytes = vec![].into();
-        bytes.put_varint(1 + packet.data.len() as Varint);
+        let id_len = VarInt(packet.id).encoded_len();
+        bytes.put_varint((id_len + packet.data.len()) as i32);
         bytes.put_varint(packet.id);
         bytes.put_bytebuffer(packet.data);
 
@@ -1,7 +1,10 @@
-use bytes::{Buf, BufMut};
+use bytes::BufMut;
 use serde::Serialize;
 
-use crate::{error::Error, report::mc::bytes::Bytes};
+use crate::{
+    error::Error,
+    report::mc::{bytes::Bytes, latency::Latency},
+};
 
 use super::{Packet, SendPacket};
 
@@ -21,7 +24,8 @@ impl From<PingRequest> for Packet {
 
 #[derive(Serialize)]
 pub struct PingResponse {
-    payload: i64,
+    pub payload: i64,
+    pub latency: Latency,
 }
 
 impl TryFrom<Packet> for PingResponse {
@@ -30,8 +34,11 @@ impl TryFrom<Packet> for PingResponse {
     fn try_from(mut packet: Packet) -> Result<Self, Self::Error> {
         match packet.id {
             0x01 => {
-                let payload = packet.data.get_i64();
-                Ok(Self { payload })
+                let payload = packet.data.get_i64()?;
+                Ok(Self {
+                    payload,
+                    latency: Latency::default(),
+                })
             }
             _ => Err(Error::Generic("wrong packet id".to_owned())),
         }
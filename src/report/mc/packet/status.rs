@@ -1,11 +1,14 @@
 use std::collections::HashMap;
 
-use bytes::Buf;
 use serde::{Deserialize, Serialize};
 
 use crate::{
     error::Error,
-    report::mc::bytes::{Bytes, Varint},
+    report::mc::{
+        bytes::{Bytes, DecodeError},
+        favicon::Favicon,
+        latency::Latency,
+    },
 };
 
 use super::{Packet, SendPacket};
@@ -32,30 +35,55 @@ impl From<StatusRequest> for Packet {
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct StatusResponse {
+    pub server_type: ServerType,
     pub previews_chat: bool,
     pub enforces_secure_chat: bool,
     pub description: Description,
     pub players: Players,
     pub version: Version,
-    pub favicon: String,
-    pub forge_data: ForgeData,
+    pub favicon: Option<Favicon>,
+    pub forge_data: Option<ForgeData>,
     pub prevents_chat_reports: bool,
+    pub latency: Latency,
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ServerType {
+    Vanilla,
+    Forge,
+    NeoForge,
+    Paper,
+    Spigot,
+}
+
+#[derive(Serialize)]
 pub struct Description {
     pub text: String,
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Clone, Default, Deserialize, Serialize)]
 pub struct Players {
+    #[serde(default)]
     pub max: i32,
+    #[serde(default)]
     pub online: i32,
+    #[serde(default)]
+    pub sample: Vec<PlayerSample>,
 }
-#[derive(Deserialize, Serialize)]
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct PlayerSample {
+    pub name: String,
+    pub id: String,
+}
+
+#[derive(Default, Deserialize, Serialize)]
 pub struct Version {
+    #[serde(default)]
     pub name: String,
-    pub protocol: Varint,
+    #[serde(default)]
+    pub protocol: i32,
 }
 
 #[derive(Serialize)]
@@ -68,68 +96,300 @@ pub struct ForgeData {
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ForgeDataD {
+    /// Set when the server left mods out to keep the response small.
     pub truncated: bool,
-    pub mods_size: u16,
-    pub mods: HashMap<String, String>,
-    pub channels: HashMap<ResourceLocation, (String, bool)>,
+    pub mods_size: usize,
+    pub mods: Vec<ForgeMod>,
+    /// Channels that don't belong to a mod, such as `minecraft:register`.
+    pub channels: Vec<ForgeChannel>,
+}
+
+impl ForgeDataD {
+    pub fn get_mod(&self, id: &str) -> Option<&ForgeMod> {
+        self.mods.iter().find(|forge_mod| forge_mod.id == id)
+    }
 }
 
-#[derive(PartialEq, Eq, Hash)]
-pub struct ResourceLocation {
-    pub mod_id: String,
-    pub channel_name: String,
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ForgeMod {
+    pub id: String,
+    /// `None` for mods marked IGNORESERVERONLY, the server doesn't send their
+    /// version.
+    pub version: Option<String>,
+    /// Set for mods marked IGNORESERVERONLY, which accept clients with any
+    /// version of the mod or without it.
+    pub ignore_server_only: bool,
+    pub channels: Vec<ForgeChannel>,
+    /// Whether players have to install the mod, either because it checks the
+    /// client's version or because one of its channels is required.
+    pub required_on_client: bool,
 }
 
-impl Serialize for ResourceLocation {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: serde::Serializer,
-    {
-        let format = format!("{}:{}", self.channel_name, self.mod_id);
-        serializer.serialize_str(&format)
+impl ForgeMod {
+    fn new(id: String, version: Option<String>, channels: Vec<ForgeChannel>) -> Self {
+        let ignore_server_only = version.is_none();
+        let required_on_client =
+            !ignore_server_only || channels.iter().any(|channel| channel.required_on_client);
+        Self {
+            id,
+            version,
+            ignore_server_only,
+            channels,
+            required_on_client,
+        }
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ForgeChannel {
+    /// Resource location of the channel, e.g. `jei:channel`.
+    pub name: String,
+    pub version: String,
+    pub required_on_client: bool,
+}
+
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct UnparsedStatusResponse {
+    #[serde(default)]
     previews_chat: bool,
+    #[serde(default)]
     enforces_secure_chat: bool,
-    description: Description,
+    #[serde(default)]
+    description: UnparsedDescription,
+    #[serde(default)]
     players: Players,
+    #[serde(default)]
     version: Version,
-    favicon: String,
-    forge_data: UnparsedForgeData,
+    favicon: Option<String>,
+    forge_data: Option<UnparsedForgeData>,
+    modinfo: Option<UnparsedModInfo>,
+    is_modded: Option<bool>,
+    #[serde(default)]
     prevents_chat_reports: bool,
 }
 
+/// Older servers send the description as a plain string, newer ones as a chat
+/// component whose text may be split across nested `extra` components.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum UnparsedDescription {
+    Text(String),
+    Component {
+        #[serde(default)]
+        text: String,
+        #[serde(default)]
+        extra: Vec<UnparsedDescription>,
+    },
+}
+
+impl Default for UnparsedDescription {
+    fn default() -> Self {
+        UnparsedDescription::Text(String::new())
+    }
+}
+
+impl UnparsedDescription {
+    fn flatten_into(self, out: &mut String) {
+        match self {
+            UnparsedDescription::Text(text) => out.push_str(&text),
+            UnparsedDescription::Component { text, extra } => {
+                out.push_str(&text);
+                for component in extra {
+                    component.flatten_into(out);
+                }
+            }
+        }
+    }
+}
+
+impl From<UnparsedDescription> for Description {
+    fn from(ud: UnparsedDescription) -> Self {
+        let mut text = String::new();
+        ud.flatten_into(&mut text);
+        Self { text }
+    }
+}
+
+/// `forgeData` as sent by FML2 (1.13 - 1.17, plain `mods` and `channels` lists)
+/// and FML3+ (1.18+ and NeoForge, everything packed into the optimized `d` string).
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct UnparsedForgeData {
     fml_network_version: i32,
-    d: String,
+    d: Option<String>,
+    #[serde(default)]
+    truncated: bool,
+    #[serde(default)]
+    mods: Vec<UnparsedForgeMod>,
+    #[serde(default)]
+    channels: Vec<UnparsedForgeChannel>,
 }
 
-impl From<UnparsedStatusResponse> for StatusResponse {
-    fn from(p: UnparsedStatusResponse) -> Self {
-        Self {
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UnparsedForgeMod {
+    mod_id: String,
+    #[serde(default)]
+    modmarker: String,
+}
+
+#[derive(Deserialize)]
+struct UnparsedForgeChannel {
+    res: String,
+    #[serde(default)]
+    version: String,
+    #[serde(default)]
+    required: bool,
+}
+
+/// `modinfo` as sent by FML1 (1.7 - 1.12) servers.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UnparsedModInfo {
+    #[serde(rename = "type", default)]
+    mod_type: String,
+    #[serde(default)]
+    mod_list: Vec<UnparsedModInfoMod>,
+}
+
+#[derive(Deserialize)]
+struct UnparsedModInfoMod {
+    modid: String,
+    #[serde(default)]
+    version: String,
+}
+
+impl TryFrom<UnparsedStatusResponse> for StatusResponse {
+    type Error = DecodeError;
+
+    fn try_from(p: UnparsedStatusResponse) -> Result<Self, Self::Error> {
+        let forge_data = match (p.forge_data, p.modinfo) {
+            (Some(forge_data), _) => Some(forge_data.try_into()?),
+            (None, Some(modinfo)) if modinfo.mod_type == "FML" => Some(modinfo.into()),
+            _ => None,
+        };
+        let server_type = ServerType::detect(&p.version, forge_data.as_ref(), p.is_modded);
+
+        Ok(Self {
+            server_type,
             previews_chat: p.previews_chat,
             enforces_secure_chat: p.enforces_secure_chat,
-            description: p.description,
+            description: p.description.into(),
             players: p.players,
             version: p.version,
-            favicon: p.favicon,
-            forge_data: p.forge_data.into(),
+            favicon: p.favicon.and_then(|data_url| {
+                Favicon::from_data_url(&data_url)
+                    .map_err(|err| tracing::warn!("failed to decode favicon: {err}"))
+                    .ok()
+            }),
+            forge_data,
             prevents_chat_reports: p.prevents_chat_reports,
+            latency: Latency::default(),
+        })
+    }
+}
+
+impl ServerType {
+    /// Fabric and Quilt servers answer exactly like vanilla ones, so they are
+    /// reported as vanilla.
+    fn detect(version: &Version, forge_data: Option<&ForgeData>, is_modded: Option<bool>) -> Self {
+        if let Some(forge_data) = forge_data {
+            if forge_data.d.get_mod("neoforge").is_some() {
+                return ServerType::NeoForge;
+            }
+            return ServerType::Forge;
+        }
+
+        let name = version.name.to_lowercase();
+        if name.contains("neoforge") || is_modded == Some(true) {
+            ServerType::NeoForge
+        } else if name.contains("forge") {
+            ServerType::Forge
+        } else if ["paper", "purpur", "folia"]
+            .iter()
+            .any(|n| name.contains(n))
+        {
+            ServerType::Paper
+        } else if ["spigot", "bukkit"].iter().any(|n| name.contains(n)) {
+            ServerType::Spigot
+        } else {
+            ServerType::Vanilla
         }
     }
 }
 
-impl From<UnparsedForgeData> for ForgeData {
-    fn from(ufd: UnparsedForgeData) -> Self {
-        Self {
+impl TryFrom<UnparsedForgeData> for ForgeData {
+    type Error = DecodeError;
+
+    fn try_from(ufd: UnparsedForgeData) -> Result<Self, Self::Error> {
+        let d = match ufd.d {
+            Some(d) => decode_forge_data_d(d)?,
+            None => {
+                let mut mod_channels = HashMap::<String, Vec<ForgeChannel>>::new();
+                let mut channels = vec![];
+                for c in ufd.channels {
+                    let channel = ForgeChannel {
+                        required_on_client: c.required,
+                        version: c.version,
+                        name: c.res,
+                    };
+                    let namespace = channel.name.split_once(':').map(|(namespace, _)| namespace);
+                    match namespace {
+                        Some(namespace) if ufd.mods.iter().any(|m| m.mod_id == namespace) => {
+                            mod_channels
+                                .entry(namespace.to_string())
+                                .or_default()
+                                .push(channel)
+                        }
+                        _ => channels.push(channel),
+                    }
+                }
+
+                let mods: Vec<_> = ufd
+                    .mods
+                    .into_iter()
+                    .map(|m| {
+                        let version = (!m.modmarker.starts_with(FML2_IGNORESERVERONLY))
+                            .then_some(m.modmarker);
+                        let channels = mod_channels.remove(&m.mod_id).unwrap_or_default();
+                        ForgeMod::new(m.mod_id, version, channels)
+                    })
+                    .collect();
+                ForgeDataD {
+                    truncated: ufd.truncated,
+                    mods_size: mods.len(),
+                    mods,
+                    channels,
+                }
+            }
+        };
+
+        Ok(Self {
             fml_network_version: ufd.fml_network_version,
-            d: decode_forge_data_d(ufd.d),
+            d,
+        })
+    }
+}
+
+impl From<UnparsedModInfo> for ForgeData {
+    fn from(umi: UnparsedModInfo) -> Self {
+        let mods: Vec<_> = umi
+            .mod_list
+            .into_iter()
+            .map(|m| ForgeMod::new(m.modid, Some(m.version), vec![]))
+            .collect();
+
+        Self {
+            fml_network_version: 1,
+            d: ForgeDataD {
+                truncated: false,
+                mods_size: mods.len(),
+                mods,
+                channels: vec![],
+            },
         }
     }
 }
@@ -143,74 +403,78 @@ impl TryFrom<Packet> for StatusResponse {
         }
 
         let unparsed_status_response: UnparsedStatusResponse =
-            serde_json::from_str(&packet.data.get_string())?;
+            serde_json::from_str(&packet.data.get_string()?)?;
 
-        Ok(unparsed_status_response.into())
+        Ok(unparsed_status_response.try_into()?)
     }
 }
 
-const VERSION_FLAG_IGNORESERVERONLY: i64 = 0b1;
-const IGNORESERVERONLY: &str = "not specified";
-fn decode_forge_data_d(d: String) -> ForgeDataD {
-    let decoded_data = decode_optimized(d);
+const VERSION_FLAG_IGNORESERVERONLY: i32 = 0b1;
+/// FML2 sends IGNORESERVERONLY as a `modmarker` starting with this.
+const FML2_IGNORESERVERONLY: &str = "OHNOES";
+pub fn decode_forge_data_d(d: String) -> Result<ForgeDataD, DecodeError> {
+    let decoded_data = decode_optimized(d)?;
     let mut d: Bytes = decoded_data.into();
-    let truncated = d.get_bool();
-    let mods_size = d.get_u16();
-
-    let mut mods = HashMap::new();
-    let mut channels = HashMap::new();
+    let truncated = d.get_bool()?;
+    let mods_size = d.get_u16()?.into();
 
+    let mut mods = Vec::with_capacity(mods_size);
     for _ in 0..mods_size {
-        let channel_size_and_version_flag = d.get_varint();
+        let channel_size_and_version_flag = d.get_varint()?;
         let channel_size = channel_size_and_version_flag >> 1;
         let is_ignore_server_only =
             (channel_size_and_version_flag & VERSION_FLAG_IGNORESERVERONLY) != 0;
 
-        let mod_id = d.get_string();
+        let mod_id = d.get_string()?;
         let mod_version = if is_ignore_server_only {
-            IGNORESERVERONLY.to_string()
+            None
         } else {
-            d.get_string()
+            Some(d.get_string()?)
         };
 
+        let mut channels = vec![];
         for _ in 0..channel_size {
-            let channel_name = d.get_string();
-            let channel_version = d.get_string();
-            let required_on_client = d.get_bool();
-            let res_loc = ResourceLocation {
-                mod_id: mod_id.clone(),
-                channel_name,
-            };
-            let pair = (channel_version, required_on_client);
-            channels.insert(res_loc, pair);
+            // mod channels only send their path, the namespace is the mod id
+            let channel_path = d.get_string()?;
+            channels.push(ForgeChannel {
+                name: format!("{mod_id}:{channel_path}"),
+                version: d.get_string()?,
+                required_on_client: d.get_bool()?,
+            });
         }
 
-        mods.insert(mod_id, mod_version);
+        mods.push(ForgeMod::new(mod_id, mod_version, channels));
     }
 
-    let non_mod_channel_count = d.get_varint();
+    let non_mod_channel_count = d.get_varint()?;
+    let mut channels = vec![];
     for _ in 0..non_mod_channel_count {
-        let res_loc = d.get_string();
-        let res_loc_data: Vec<&str> = res_loc.split(':').collect();
-        let channel_name = ResourceLocation {
-            channel_name: res_loc_data[0].to_string(),
-            mod_id: res_loc_data[1].to_string(),
-        };
-
-        let channel_version = d.get_string();
-        let required_on_client = d.get_bool();
+        let name = d.get_string()?;
+        if !name.contains(':') {
+            return Err(DecodeError::InvalidResourceLocation(name));
+        }
 
-        channels.insert(channel_name, (channel_version, required_on_client));
+        channels.push(ForgeChannel {
+            name,
+            version: d.get_string()?,
+            required_on_client: d.get_bool()?,
+        });
     }
-    ForgeDataD {
+    Ok(ForgeDataD {
         truncated,
         mods_size,
         mods,
         channels,
-    }
+    })
 }
-fn decode_optimized(s: String) -> Vec<u8> {
+fn decode_optimized(s: String) -> Result<Vec<u8>, DecodeError> {
     let data: Vec<u16> = s.encode_utf16().collect();
+    if data.len() < 2 {
+        return Err(DecodeError::UnexpectedEof {
+            needed: 2,
+            remaining: data.len(),
+        });
+    }
     let mut current = &data[..];
 
     let size0 = (current[0] & 0x7FFF) as u32;
@@ -220,6 +484,11 @@ fn decode_optimized(s: String) -> Vec<u8> {
 
     current = &current[2..];
 
+    // every char carries 15 bits, a larger size can't be backed by the data
+    if size * 8 > current.len() * 15 {
+        return Err(DecodeError::InvalidLength(size as i64));
+    }
+
     let mut buf = Vec::with_capacity(size);
 
     let mut buffer: u32 = 0;
@@ -248,5 +517,5 @@ fn decode_optimized(s: String) -> Vec<u8> {
         }
     }
 
-    buf
+    Ok(buf)
 }
@@ -0,0 +1,299 @@
+//! Query protocol (GameSpy4 over UDP), answered by servers with
+//! `enable-query=true`. See <https://wiki.vg/Query>.
+
+use std::{
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use bytes::BufMut;
+use serde::Serialize;
+
+use crate::error::Error;
+
+use super::{bytes::Bytes, Result};
+
+const MAGIC: u16 = 0xFEFD;
+const TYPE_HANDSHAKE: u8 = 0x09;
+const TYPE_STAT: u8 = 0x00;
+const SESSION_ID_MASK: i32 = 0x0F0F0F0F;
+const FULL_STAT_PADDING: usize = 11;
+const PLAYERS_PADDING: usize = 10;
+/// A full stat with a long player list can fill a whole UDP datagram.
+const MAX_DATAGRAM_SIZE: usize = 65535;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BasicStat {
+    pub motd: String,
+    pub game_type: String,
+    pub map: String,
+    pub num_players: i32,
+    pub max_players: i32,
+    pub host_port: u16,
+    pub host_ip: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FullStat {
+    pub motd: String,
+    pub game_type: String,
+    pub game_id: String,
+    pub version: String,
+    pub server_mod: Option<String>,
+    pub plugins: Vec<String>,
+    pub map: String,
+    pub num_players: i32,
+    pub max_players: i32,
+    pub host_port: u16,
+    pub host_ip: String,
+    pub players: Vec<String>,
+}
+
+/// Address the server answers queries on, `MC_QUERY_PORT` on the server
+/// address, defaulting to the game port like `query.port` does.
+pub fn query_addr() -> Result<SocketAddr> {
+    let mut addr = super::server_addr()?;
+    if let Ok(port) = std::env::var("MC_QUERY_PORT") {
+        addr.set_port(port.parse()?);
+    }
+    Ok(addr)
+}
+
+pub fn basic_stat(addr: SocketAddr) -> Result<BasicStat> {
+    let mut session = Session::connect(addr)?;
+    let token = session.challenge_token()?;
+    let mut response = session.request(TYPE_STAT, Some(token), false)?;
+
+    Ok(BasicStat {
+        motd: response.get_cstring()?,
+        game_type: response.get_cstring()?,
+        map: response.get_cstring()?,
+        num_players: response.get_cstring()?.parse()?,
+        max_players: response.get_cstring()?.parse()?,
+        host_port: response.get_u16_le()?,
+        host_ip: response.get_cstring()?,
+    })
+}
+
+pub fn full_stat(addr: SocketAddr) -> Result<FullStat> {
+    let mut session = Session::connect(addr)?;
+    let token = session.challenge_token()?;
+    let mut response = session.request(TYPE_STAT, Some(token), true)?;
+
+    response.skip(FULL_STAT_PADDING)?;
+    let mut full_stat = FullStat {
+        motd: String::new(),
+        game_type: String::new(),
+        game_id: String::new(),
+        version: String::new(),
+        server_mod: None,
+        plugins: vec![],
+        map: String::new(),
+        num_players: 0,
+        max_players: 0,
+        host_port: 0,
+        host_ip: String::new(),
+        players: vec![],
+    };
+    loop {
+        let key = response.get_cstring()?;
+        if key.is_empty() {
+            break;
+        }
+        let value = response.get_cstring()?;
+        match key.as_str() {
+            "hostname" => full_stat.motd = value,
+            "gametype" => full_stat.game_type = value,
+            "game_id" => full_stat.game_id = value,
+            "version" => full_stat.version = value,
+            "plugins" => (full_stat.server_mod, full_stat.plugins) = parse_plugins(&value),
+            "map" => full_stat.map = value,
+            "numplayers" => full_stat.num_players = value.parse()?,
+            "maxplayers" => full_stat.max_players = value.parse()?,
+            "hostport" => full_stat.host_port = value.parse()?,
+            "hostip" => full_stat.host_ip = value,
+            _ => tracing::debug!("ignoring unknown query key `{key}`"),
+        }
+    }
+
+    response.skip(PLAYERS_PADDING)?;
+    loop {
+        let player = response.get_cstring()?;
+        if player.is_empty() {
+            break;
+        }
+        full_stat.players.push(player);
+    }
+
+    Ok(full_stat)
+}
+
+/// Plugins are sent as `<server mod>: <plugin>; <plugin>`, or empty on vanilla.
+fn parse_plugins(plugins: &str) -> (Option<String>, Vec<String>) {
+    match plugins.split_once(':') {
+        Some((server_mod, plugins)) => (
+            Some(server_mod.trim().to_owned()),
+            plugins
+                .split(';')
+                .map(str::trim)
+                .filter(|plugin| !plugin.is_empty())
+                .map(str::to_owned)
+                .collect(),
+        ),
+        None if plugins.trim().is_empty() => (None, vec![]),
+        None => (Some(plugins.trim().to_owned()), vec![]),
+    }
+}
+
+struct Session {
+    socket: UdpSocket,
+    id: i32,
+}
+
+impl Session {
+    fn connect(addr: SocketAddr) -> Result<Self> {
+        let local_ip = match addr {
+            SocketAddr::V4(_) => Ipv4Addr::UNSPECIFIED.into(),
+            SocketAddr::V6(_) => Ipv6Addr::UNSPECIFIED.into(),
+        };
+        let socket = UdpSocket::bind(SocketAddr::new(local_ip, 0))?;
+        let five_sec = Duration::from_secs(5);
+        socket.set_read_timeout(Some(five_sec))?;
+        socket.set_write_timeout(Some(five_sec))?;
+        socket.connect(addr)?;
+
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.subsec_nanos())
+            .unwrap_or_default();
+
+        Ok(Self {
+            socket,
+            id: nanos as i32 & SESSION_ID_MASK,
+        })
+    }
+
+    fn challenge_token(&mut self) -> Result<i32> {
+        let mut response = self.request(TYPE_HANDSHAKE, None, false)?;
+        Ok(response.get_cstring()?.parse()?)
+    }
+
+    /// Sends a request and returns the response payload after its header.
+    fn request(&mut self, request_type: u8, token: Option<i32>, full: bool) -> Result<Bytes> {
+        let mut request: Bytes = vec![].into();
+        request.put_u16(MAGIC);
+        request.put_u8(request_type);
+        request.put_i32(self.id);
+        if let Some(token) = token {
+            request.put_i32(token);
+        }
+        if full {
+            request.put_i32(0);
+        }
+        let request: Vec<u8> = request.into();
+        self.socket.send(&request)?;
+
+        let mut buffer = vec![0u8; MAX_DATAGRAM_SIZE];
+        let size = self.socket.recv(&mut buffer)?;
+        buffer.truncate(size);
+
+        let mut response: Bytes = buffer.into();
+        if response.get_u8()? != request_type || response.get_i32()? != self.id {
+            return Err(Error::Generic("unexpected query response".to_owned()));
+        }
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::IpAddr, thread};
+
+    use super::*;
+
+    const CHALLENGE_TOKEN: i32 = 9513307;
+
+    /// Answers one handshake and one stat request the way a vanilla server does.
+    fn spawn_server(stat: &'static [u8]) -> SocketAddr {
+        spawn_server_on(Ipv4Addr::LOCALHOST.into(), stat)
+    }
+
+    fn spawn_server_on(ip: IpAddr, stat: &'static [u8]) -> SocketAddr {
+        let socket = UdpSocket::bind((ip, 0)).unwrap();
+        let addr = socket.local_addr().unwrap();
+
+        thread::spawn(move || {
+            let mut buffer = [0u8; 64];
+            for _ in 0..2 {
+                let (size, client) = socket.recv_from(&mut buffer).unwrap();
+                let mut request: Bytes = buffer[..size].into();
+                assert_eq!(request.get_u16().unwrap(), MAGIC);
+                let request_type = request.get_u8().unwrap();
+                let session_id = request.get_i32().unwrap();
+
+                let mut response = vec![request_type];
+                response.extend(session_id.to_be_bytes());
+                if request_type == TYPE_HANDSHAKE {
+                    response.extend(format!("{CHALLENGE_TOKEN}\0").as_bytes());
+                } else {
+                    assert_eq!(request.get_i32().unwrap(), CHALLENGE_TOKEN);
+                    response.extend(stat);
+                }
+                socket.send_to(&response, client).unwrap();
+            }
+        });
+
+        addr
+    }
+
+    #[test]
+    fn basic_stat_response() {
+        let addr = spawn_server(b"A Minecraft Server\0SMP\0world\x002\x0020\0\xdd\x63127.0.0.1\0");
+
+        let stat = basic_stat(addr).unwrap();
+        assert_eq!(stat.motd, "A Minecraft Server");
+        assert_eq!(stat.game_type, "SMP");
+        assert_eq!(stat.num_players, 2);
+        assert_eq!(stat.max_players, 20);
+        assert_eq!(stat.host_port, 25565);
+        assert_eq!(stat.host_ip, "127.0.0.1");
+    }
+
+    #[test]
+    fn full_stat_response() {
+        let addr = spawn_server(
+            b"splitnum\0\x80\0\
+              hostname\0A Minecraft Server\0gametype\0SMP\0game_id\0MINECRAFT\0\
+              version\x001.20.1\0plugins\0Paper on 1.20.1: WorldEdit 7.2.15; Essentials 2.20\0\
+              map\0world\0numplayers\x002\0maxplayers\x0020\0hostport\x0025565\0\
+              hostip\x00127.0.0.1\0\0\
+              \x01player_\0\0Notch\0jeb_\0\0",
+        );
+
+        let stat = full_stat(addr).unwrap();
+        assert_eq!(stat.version, "1.20.1");
+        assert_eq!(stat.map, "world");
+        assert_eq!(stat.server_mod.as_deref(), Some("Paper on 1.20.1"));
+        assert_eq!(stat.plugins, ["WorldEdit 7.2.15", "Essentials 2.20"]);
+        assert_eq!(stat.num_players, 2);
+        assert_eq!(stat.players, ["Notch", "jeb_"]);
+    }
+
+    #[test]
+    fn ipv6_server() {
+        let addr = spawn_server_on(
+            Ipv6Addr::LOCALHOST.into(),
+            b"A Minecraft Server\0SMP\0world\x000\x0020\0\xdd\x63::1\0",
+        );
+
+        let stat = basic_stat(addr).unwrap();
+        assert_eq!(stat.host_ip, "::1");
+    }
+
+    #[test]
+    fn vanilla_has_no_plugins() {
+        assert_eq!(parse_plugins(""), (None, vec![]));
+    }
+}
@@ -0,0 +1,507 @@
+//! Source RCON as implemented by Minecraft servers with `enable-rcon=true`.
+//! See <https://wiki.vg/RCON>.
+
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    sync::Mutex,
+};
+
+use crate::{error::Error, response::Response};
+
+use super::Result;
+
+const TYPE_RESPONSE: i32 = 0;
+const TYPE_COMMAND: i32 = 2;
+const TYPE_LOGIN: i32 = 3;
+/// id, type and the two null terminators.
+const MIN_PACKET_LENGTH: i32 = 10;
+const MAX_PACKET_LENGTH: i32 = MIN_PACKET_LENGTH + 4096;
+const TIMEOUT: Duration = Duration::from_secs(5);
+const DEFAULT_RCON_PORT: u16 = 25575;
+const DEFAULT_ALLOWED_COMMANDS: &[&str] = &[
+    "list",
+    "whitelist list",
+    "whitelist add",
+    "whitelist remove",
+    "say",
+];
+
+pub type SharedRcon = Arc<Mutex<RconClient>>;
+
+/// Keeps one logged in connection around and reconnects when it breaks.
+#[derive(Debug, Default)]
+pub struct RconClient {
+    stream: Option<TcpStream>,
+    next_id: i32,
+}
+
+struct RconPacket {
+    id: i32,
+    packet_type: i32,
+    payload: String,
+}
+
+/// `MC_RCON_PORT` on the server address, 25575 by default like `rcon.port`.
+fn rcon_addr() -> Result<SocketAddr> {
+    let mut addr = super::server_addr()?;
+    let port = match std::env::var("MC_RCON_PORT") {
+        Ok(port) => port.parse()?,
+        Err(_) => DEFAULT_RCON_PORT,
+    };
+    addr.set_port(port);
+    Ok(addr)
+}
+
+impl RconClient {
+    /// Runs `command` on the server at `MC_RCON_PORT`, logging in with
+    /// `MC_RCON_PASSWORD`.
+    pub async fn command(&mut self, command: &str) -> Result<String> {
+        let addr = rcon_addr()?;
+        let password = std::env::var("MC_RCON_PASSWORD")?;
+        self.command_at(addr, &password, command).await
+    }
+
+    /// Runs `command`, reconnecting first if the server closed the connection.
+    /// A command is never sent twice, if the connection breaks after sending
+    /// it the server may have run it already.
+    pub async fn command_at(
+        &mut self,
+        addr: SocketAddr,
+        password: &str,
+        command: &str,
+    ) -> Result<String> {
+        if self.stream.as_ref().is_some_and(is_closed) {
+            tracing::debug!("rcon connection closed by the server, reconnecting");
+            self.stream = None;
+        }
+        if self.stream.is_none() {
+            self.connect(addr, password).await?;
+        }
+
+        let result = self.exec(command).await;
+        if result.is_err() {
+            self.stream = None;
+        }
+        result
+    }
+
+    /// Keeps the connection only once the server accepted the password.
+    async fn connect(&mut self, addr: SocketAddr, password: &str) -> Result<()> {
+        let mut stream = tokio::time::timeout(TIMEOUT, TcpStream::connect(addr))
+            .await
+            .map_err(|_| Error::Generic("rcon connection timed out".to_owned()))??;
+
+        let id = self.next_id();
+        send(&mut stream, id, TYPE_LOGIN, password).await?;
+        let response = recv(&mut stream).await?;
+        if response.id != id {
+            return Err(Error::Generic("rcon authentication failed".to_owned()));
+        }
+        self.stream = Some(stream);
+        Ok(())
+    }
+
+    /// Responses longer than one packet are split without any marker, so an
+    /// empty response packet is sent after the command. The server answers
+    /// requests in order, so its reply marks the end of the command output.
+    async fn exec(&mut self, command: &str) -> Result<String> {
+        let id = self.next_id();
+        let end_id = self.next_id();
+        let stream = self
+            .stream
+            .as_mut()
+            .ok_or_else(|| Error::Generic("rcon is not connected".to_owned()))?;
+        send(stream, id, TYPE_COMMAND, command).await?;
+        send(stream, end_id, TYPE_RESPONSE, "").await?;
+
+        let mut output = String::new();
+        loop {
+            let packet = recv(stream).await?;
+            if packet.id == end_id {
+                return Ok(output);
+            }
+            if packet.id != id || packet.packet_type != TYPE_RESPONSE {
+                return Err(Error::Generic("unexpected rcon packet".to_owned()));
+            }
+            output.push_str(&packet.payload);
+        }
+    }
+
+    fn next_id(&mut self) -> i32 {
+        // ids stay positive, -1 means failed authentication
+        self.next_id = self.next_id.checked_add(1).unwrap_or(1);
+        self.next_id
+    }
+}
+
+/// Whether the server closed the connection or sent something while no
+/// command was running, either way it can't be used for the next one.
+fn is_closed(stream: &TcpStream) -> bool {
+    match stream.try_read(&mut [0u8; 1]) {
+        Err(err) => err.kind() != std::io::ErrorKind::WouldBlock,
+        Ok(_) => true,
+    }
+}
+
+async fn send(stream: &mut TcpStream, id: i32, packet_type: i32, payload: &str) -> Result<()> {
+    let length = MIN_PACKET_LENGTH + payload.len() as i32;
+    let mut packet = Vec::with_capacity(4 + length as usize);
+    packet.extend(length.to_le_bytes());
+    packet.extend(id.to_le_bytes());
+    packet.extend(packet_type.to_le_bytes());
+    packet.extend(payload.as_bytes());
+    packet.extend([0, 0]);
+
+    tokio::time::timeout(TIMEOUT, stream.write_all(&packet))
+        .await
+        .map_err(|_| Error::Generic("rcon send timed out".to_owned()))??;
+    Ok(())
+}
+
+async fn recv(stream: &mut TcpStream) -> Result<RconPacket> {
+    tokio::time::timeout(TIMEOUT, async {
+        let length = stream.read_i32_le().await?;
+        if !(MIN_PACKET_LENGTH..=MAX_PACKET_LENGTH).contains(&length) {
+            return Err(Error::Generic(format!(
+                "invalid rcon packet length {length}"
+            )));
+        }
+        let id = stream.read_i32_le().await?;
+        let packet_type = stream.read_i32_le().await?;
+        let mut payload = vec![0u8; (length - 8) as usize];
+        stream.read_exact(&mut payload).await?;
+        payload.truncate(payload.len() - 2);
+
+        Ok(RconPacket {
+            id,
+            packet_type,
+            payload: String::from_utf8_lossy(&payload).into(),
+        })
+    })
+    .await
+    .map_err(|_| Error::Generic("rcon receive timed out".to_owned()))?
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RconRequest {
+    pub command: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RconOutput {
+    pub output: String,
+    pub parsed: Option<ParsedOutput>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ParsedOutput {
+    List {
+        online: i32,
+        max: i32,
+        players: Vec<String>,
+    },
+    Whitelist {
+        players: Vec<String>,
+    },
+}
+
+/// Commands allowed through the HTTP endpoint, `RCON_ALLOWED_COMMANDS` as a
+/// comma separated list of command prefixes.
+fn allowed_commands() -> Vec<String> {
+    match std::env::var("RCON_ALLOWED_COMMANDS") {
+        Ok(commands) => commands
+            .split(',')
+            .map(|command| command.trim().to_owned())
+            .filter(|command| !command.is_empty())
+            .collect(),
+        Err(_) => DEFAULT_ALLOWED_COMMANDS
+            .iter()
+            .map(|command| command.to_string())
+            .collect(),
+    }
+}
+
+fn is_allowed(command: &str, allowed_commands: &[String]) -> bool {
+    if command.chars().any(char::is_control) {
+        return false;
+    }
+
+    allowed_commands.iter().any(|allowed| {
+        command
+            .strip_prefix(allowed.as_str())
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with(' '))
+    })
+}
+
+/// Compares the bearer token with `RCON_API_TOKEN`, without a configured token
+/// the endpoint is disabled.
+pub fn is_authorized(token: Option<&str>) -> bool {
+    let (Ok(expected), Some(token)) = (std::env::var("RCON_API_TOKEN"), token) else {
+        return false;
+    };
+    !expected.is_empty()
+        && expected.len() == token.len()
+        && expected
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+fn strip_formatting(output: &str) -> String {
+    let mut stripped = String::with_capacity(output.len());
+    let mut chars = output.chars();
+    while let Some(c) = chars.next() {
+        if c == '§' {
+            chars.next();
+        } else {
+            stripped.push(c);
+        }
+    }
+    stripped
+}
+
+fn parse_player_names(names: &str) -> Vec<String> {
+    names
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+fn parse_output(command: &str, output: &str) -> Option<ParsedOutput> {
+    let output = strip_formatting(output);
+    let (summary, names) = output.split_once(':').unwrap_or((&output, ""));
+    let numbers: Vec<i32> = summary
+        .split(|c: char| !c.is_ascii_digit())
+        .filter_map(|number| number.parse().ok())
+        .collect();
+
+    match command.trim() {
+        // There are 2 of a max of 20 players online: Notch, jeb_
+        "list" => match numbers[..] {
+            [online, max, ..] => Some(ParsedOutput::List {
+                online,
+                max,
+                players: parse_player_names(names),
+            }),
+            _ => None,
+        },
+        // There are 2 whitelisted player(s): Notch, jeb_
+        "whitelist list" => Some(ParsedOutput::Whitelist {
+            players: parse_player_names(names),
+        }),
+        _ => None,
+    }
+}
+
+pub async fn command_route(rcon: SharedRcon, request: RconRequest) -> Response<RconOutput> {
+    let command = request.command.trim();
+    if !is_allowed(command, &allowed_commands()) {
+        return Response::err(Error::Generic(format!(
+            "command `{command}` is not allowed"
+        )));
+    }
+
+    tracing::debug!("running rcon command `{command}`");
+    match rcon.lock().await.command(command).await {
+        Ok(output) => Response::ok(RconOutput {
+            parsed: parse_output(command, &output),
+            output,
+        }),
+        Err(err) => Response::err(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    const PASSWORD: &str = "hunter2";
+    const AUTH_FAILED_ID: i32 = -1;
+
+    async fn read_packet(stream: &mut TcpStream) -> (i32, i32, String) {
+        let length = stream.read_i32_le().await.unwrap();
+        let id = stream.read_i32_le().await.unwrap();
+        let packet_type = stream.read_i32_le().await.unwrap();
+        let mut payload = vec![0u8; (length - 8) as usize];
+        stream.read_exact(&mut payload).await.unwrap();
+        payload.truncate(payload.len() - 2);
+        (id, packet_type, String::from_utf8(payload).unwrap())
+    }
+
+    async fn write_packet(stream: &mut TcpStream, id: i32, packet_type: i32, payload: &str) {
+        let mut packet = (MIN_PACKET_LENGTH + payload.len() as i32)
+            .to_le_bytes()
+            .to_vec();
+        packet.extend(id.to_le_bytes());
+        packet.extend(packet_type.to_le_bytes());
+        packet.extend(payload.as_bytes());
+        packet.extend([0, 0]);
+        stream.write_all(&packet).await.unwrap();
+    }
+
+    /// Logs in and answers every command with its output split over
+    /// `chunks` packets, then closes the connection after `commands` commands.
+    async fn spawn_server(output: &'static str, chunks: usize, commands: usize) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let (id, packet_type, password) = read_packet(&mut stream).await;
+                assert_eq!(packet_type, TYPE_LOGIN);
+                let id = if password == PASSWORD {
+                    id
+                } else {
+                    AUTH_FAILED_ID
+                };
+                write_packet(&mut stream, id, TYPE_COMMAND, "").await;
+
+                for _ in 0..commands {
+                    let (id, packet_type, _) = read_packet(&mut stream).await;
+                    assert_eq!(packet_type, TYPE_COMMAND);
+                    let chunk_size = output.len().div_ceil(chunks);
+                    for chunk in output.as_bytes().chunks(chunk_size) {
+                        let chunk = std::str::from_utf8(chunk).unwrap();
+                        write_packet(&mut stream, id, TYPE_RESPONSE, chunk).await;
+                    }
+
+                    let (end_id, packet_type, _) = read_packet(&mut stream).await;
+                    assert_eq!(packet_type, TYPE_RESPONSE);
+                    write_packet(&mut stream, end_id, TYPE_RESPONSE, "Unknown request 0").await;
+                }
+            }
+        });
+
+        addr
+    }
+
+    #[tokio::test]
+    async fn multi_packet_response() {
+        let addr = spawn_server("There are 0 of a max of 20 players online: ", 3, 1).await;
+
+        let mut client = RconClient::default();
+        client.connect(addr, PASSWORD).await.unwrap();
+        let output = client.exec("list").await.unwrap();
+        assert_eq!(output, "There are 0 of a max of 20 players online: ");
+    }
+
+    #[tokio::test]
+    async fn wrong_password() {
+        let addr = spawn_server("", 1, 0).await;
+
+        let mut client = RconClient::default();
+        assert!(client.connect(addr, "wrong").await.is_err());
+        assert!(client.stream.is_none());
+    }
+
+    #[tokio::test]
+    async fn closed_during_login() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            read_packet(&mut stream).await;
+        });
+
+        let mut client = RconClient::default();
+        assert!(client.connect(addr, PASSWORD).await.is_err());
+        assert!(client.stream.is_none());
+    }
+
+    #[tokio::test]
+    async fn command_not_resent_after_sending() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let received = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let server_received = received.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let (id, _, _) = read_packet(&mut stream).await;
+                write_packet(&mut stream, id, TYPE_COMMAND, "").await;
+                // closes the connection without answering the command
+                read_packet(&mut stream).await;
+                server_received.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            }
+        });
+
+        let mut client = RconClient::default();
+        assert!(client
+            .command_at(addr, PASSWORD, "whitelist add Notch")
+            .await
+            .is_err());
+        assert_eq!(received.load(std::sync::atomic::Ordering::SeqCst), 1);
+        assert!(client.stream.is_none());
+    }
+
+    #[tokio::test]
+    async fn reconnects_after_connection_closed() {
+        let addr = spawn_server("Hello", 1, 1).await;
+
+        let mut client = RconClient::default();
+        for _ in 0..3 {
+            let output = client.command_at(addr, PASSWORD, "say Hello").await;
+            assert_eq!(output.unwrap(), "Hello");
+        }
+    }
+
+    #[test]
+    fn allowed_commands() {
+        let allowed: Vec<String> = DEFAULT_ALLOWED_COMMANDS
+            .iter()
+            .map(|command| command.to_string())
+            .collect();
+
+        assert!(is_allowed("list", &allowed));
+        assert!(is_allowed("whitelist add Notch", &allowed));
+        assert!(is_allowed("say hello there", &allowed));
+        assert!(!is_allowed("listen", &allowed));
+        assert!(!is_allowed("whitelist off", &allowed));
+        assert!(!is_allowed("op Notch", &allowed));
+        assert!(!is_allowed("say hi\nop Notch", &allowed));
+    }
+
+    #[test]
+    fn parse_list_output() {
+        assert_eq!(
+            parse_output(
+                "list",
+                "There are 2 of a max of 20 players online: Notch, jeb_"
+            ),
+            Some(ParsedOutput::List {
+                online: 2,
+                max: 20,
+                players: vec!["Notch".to_owned(), "jeb_".to_owned()],
+            })
+        );
+        assert_eq!(
+            parse_output(
+                "list",
+                "§6There are §c0§6 out of maximum §c20§6 players online."
+            ),
+            Some(ParsedOutput::List {
+                online: 0,
+                max: 20,
+                players: vec![],
+            })
+        );
+        assert_eq!(
+            parse_output("whitelist list", "There are 1 whitelisted player(s): Notch"),
+            Some(ParsedOutput::Whitelist {
+                players: vec!["Notch".to_owned()],
+            })
+        );
+        assert_eq!(parse_output("say hi", ""), None);
+    }
+}
@@ -0,0 +1,692 @@
+//! Player sessions pieced together from the player samples of status polls,
+//! kept as an append-only log of joins, leaves and heartbeats in
+//! `MC_HISTORY_DIR`. Once a day sessions past the history retention are
+//! dropped and the log is rewritten from the ones left.
+//!
+//! Servers only sample up to 12 players, so a player missing from an
+//! incomplete sample is only considered gone after [`SESSION_TIMEOUT`].
+
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{error::Error, response::Response};
+
+use super::{history::HistoryConfig, packet::status::Players, Result};
+
+const DAY: u64 = 24 * 60 * 60;
+/// How long a player missing from incomplete samples still counts as online.
+pub const SESSION_TIMEOUT: u64 = 5 * 60;
+/// How often the players still online are logged, a restart ends their
+/// sessions at the last heartbeat.
+const HEARTBEAT_INTERVAL: u64 = 60;
+/// Sent in place of players that hide themselves from the sample.
+const ANONYMOUS_ID: &str = "00000000-0000-0000-0000-000000000000";
+const DEFAULT_TOP_LIMIT: usize = 10;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "camelCase")]
+enum Event {
+    Join { time: u64, id: String, name: String },
+    Leave { time: u64, id: String },
+    Seen { time: u64, ids: Vec<String> },
+}
+
+#[derive(Debug, Clone)]
+struct OnlinePlayer {
+    name: String,
+    since: u64,
+    last_seen: u64,
+}
+
+#[derive(Debug, Clone)]
+struct Session {
+    id: String,
+    name: String,
+    start: u64,
+    end: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Sessions {
+    inner: Arc<Mutex<SessionsInner>>,
+}
+
+/// The log is only read on open, after that the sessions are kept here.
+#[derive(Debug)]
+struct SessionsInner {
+    path: PathBuf,
+    config: HistoryConfig,
+    online: HashMap<String, OnlinePlayer>,
+    /// Ended sessions, in the order they ended.
+    ended: Vec<Session>,
+    /// Time of the last successful status, `None` before the first one.
+    last_status: Option<u64>,
+    last_heartbeat: Option<u64>,
+    /// Day the log was last compacted on.
+    compacted_day: Option<u64>,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+fn read_events(path: &Path) -> Result<Vec<Event>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut events = vec![];
+    for line in BufReader::new(file).lines() {
+        match serde_json::from_str(&line?) {
+            Ok(event) => events.push(event),
+            Err(err) => tracing::warn!("skipping bad line in {}: {err}", path.display()),
+        }
+    }
+    Ok(events)
+}
+
+impl Event {
+    fn time(&self) -> u64 {
+        match self {
+            Event::Join { time, .. } | Event::Leave { time, .. } | Event::Seen { time, .. } => {
+                *time
+            }
+        }
+    }
+}
+
+impl Sessions {
+    /// Opens the sessions log in `MC_HISTORY_DIR`, `None` when it isn't set.
+    pub fn from_env() -> Result<Option<Self>> {
+        match std::env::var("MC_HISTORY_DIR") {
+            Ok(dir) => Ok(Some(Self::open(dir, HistoryConfig::from_env()?)?)),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Sessions left open by a previous run are ended at their last
+    /// heartbeat, nothing is known about the players after that.
+    pub fn open(dir: impl Into<PathBuf>, config: HistoryConfig) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        let mut inner = SessionsInner {
+            path: dir.join("sessions.jsonl"),
+            config,
+            online: HashMap::new(),
+            ended: vec![],
+            last_status: None,
+            last_heartbeat: None,
+            compacted_day: None,
+        };
+
+        let events = read_events(&inner.path)?;
+        let last_event_day = events.last().map(|event| event.time() / DAY);
+        for event in events {
+            match event {
+                Event::Join { time, id, name } => {
+                    inner.online.insert(
+                        id,
+                        OnlinePlayer {
+                            name,
+                            since: time,
+                            last_seen: time,
+                        },
+                    );
+                }
+                Event::Leave { time, id } => {
+                    inner.end(id, time);
+                }
+                Event::Seen { time, ids } => {
+                    for id in ids {
+                        if let Some(online_player) = inner.online.get_mut(&id) {
+                            online_player.last_seen = time;
+                        }
+                    }
+                }
+            }
+        }
+        inner.end_all(|online_player| online_player.last_seen)?;
+        if let Some(day) = last_event_day {
+            inner.compact(day)?;
+        }
+
+        Ok(Self {
+            inner: Arc::new(Mutex::new(inner)),
+        })
+    }
+
+    pub fn observe(&self, players: &Players) {
+        if let Err(err) = self.observe_at(now(), players) {
+            tracing::warn!("failed to record player sessions: {err}");
+        }
+    }
+
+    /// Starts sessions of newly sampled players and ends the ones of players
+    /// that are gone.
+    pub fn observe_at(&self, time: u64, players: &Players) -> Result<()> {
+        let mut inner = self
+            .inner
+            .lock()
+            .expect("sessions lock should not be poisoned");
+        let day = time / DAY;
+        if inner.compacted_day != Some(day) {
+            inner.compact(day)?;
+        }
+        inner.last_status = Some(time);
+        let mut events = vec![];
+
+        let sample: Vec<_> = players
+            .sample
+            .iter()
+            .filter(|player| player.id != ANONYMOUS_ID)
+            .collect();
+        for player in &sample {
+            match inner.online.get_mut(&player.id) {
+                Some(online_player) => online_player.last_seen = time,
+                None => {
+                    inner.online.insert(
+                        player.id.clone(),
+                        OnlinePlayer {
+                            name: player.name.clone(),
+                            since: time,
+                            last_seen: time,
+                        },
+                    );
+                    events.push(Event::Join {
+                        time,
+                        id: player.id.clone(),
+                        name: player.name.clone(),
+                    });
+                }
+            }
+        }
+
+        let complete = players.sample.len() == players.online as usize;
+        let gone: Vec<_> = inner
+            .online
+            .iter()
+            .filter_map(|(id, online_player)| {
+                if complete && online_player.last_seen != time {
+                    Some((id.clone(), time))
+                } else if time.saturating_sub(online_player.last_seen) > SESSION_TIMEOUT {
+                    Some((id.clone(), online_player.last_seen))
+                } else {
+                    None
+                }
+            })
+            .collect();
+        for (id, time) in gone {
+            inner.end(id.clone(), time);
+            events.push(Event::Leave { time, id });
+        }
+
+        let heartbeat_due = inner
+            .last_heartbeat
+            .is_none_or(|last_heartbeat| time.saturating_sub(last_heartbeat) >= HEARTBEAT_INTERVAL);
+        // players who just joined are already logged at this time
+        let seen: Vec<_> = inner
+            .online
+            .iter()
+            .filter(|(_, online_player)| {
+                online_player.last_seen == time && online_player.since != time
+            })
+            .map(|(id, _)| id.clone())
+            .collect();
+        if heartbeat_due && !seen.is_empty() {
+            inner.last_heartbeat = Some(time);
+            events.push(Event::Seen { time, ids: seen });
+        }
+
+        inner.append(&events)
+    }
+
+    pub fn poll_failed(&self) {
+        if let Err(err) = self.poll_failed_at(now()) {
+            tracing::warn!("failed to record player sessions: {err}");
+        }
+    }
+
+    /// Ends every session once no status succeeded for [`SESSION_TIMEOUT`],
+    /// the server can't be asked about its players. A single failed poll
+    /// doesn't mean everyone left.
+    pub fn poll_failed_at(&self, time: u64) -> Result<()> {
+        let mut inner = self
+            .inner
+            .lock()
+            .expect("sessions lock should not be poisoned");
+        let timed_out = inner
+            .last_status
+            .is_none_or(|last_status| time.saturating_sub(last_status) > SESSION_TIMEOUT);
+        if !timed_out {
+            return Ok(());
+        }
+        inner.end_all(|online_player| online_player.last_seen)
+    }
+
+    /// Players by time played between `from` and `to`, including sessions
+    /// that are still going on.
+    pub fn top_players(&self, from: u64, to: u64, limit: usize) -> Vec<PlayerPlaytime> {
+        let inner = self
+            .inner
+            .lock()
+            .expect("sessions lock should not be poisoned");
+
+        let mut playtimes = HashMap::<&str, PlayerPlaytime>::new();
+        let online = inner.online.iter().map(|(id, online_player)| {
+            (
+                id.as_str(),
+                online_player.name.as_str(),
+                online_player.since,
+                online_player.last_seen,
+            )
+        });
+        let ended = inner.ended.iter().map(|session| {
+            (
+                session.id.as_str(),
+                session.name.as_str(),
+                session.start,
+                session.end,
+            )
+        });
+        for (id, name, start, end) in ended.chain(online) {
+            let (start, end) = (start.max(from), end.min(to));
+            if start >= end {
+                continue;
+            }
+            let playtime = playtimes.entry(id).or_insert_with(|| PlayerPlaytime {
+                id: id.to_owned(),
+                name: name.to_owned(),
+                played_seconds: 0,
+                sessions: 0,
+            });
+            playtime.name = name.to_owned();
+            playtime.played_seconds += end - start;
+            playtime.sessions += 1;
+        }
+
+        let mut top: Vec<_> = playtimes.into_values().collect();
+        top.sort_by(|a, b| {
+            b.played_seconds
+                .cmp(&a.played_seconds)
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        top.truncate(limit);
+        top
+    }
+
+    /// Looks a player up by UUID or, case insensitively, by name.
+    pub fn last_seen(&self, player: &str) -> Option<LastSeen> {
+        let inner = self
+            .inner
+            .lock()
+            .expect("sessions lock should not be poisoned");
+        let matches = |id: &str, name: &str| id == player || name.eq_ignore_ascii_case(player);
+
+        if let Some((id, online_player)) = inner
+            .online
+            .iter()
+            .find(|(id, online_player)| matches(id, &online_player.name))
+        {
+            return Some(LastSeen {
+                id: id.clone(),
+                name: online_player.name.clone(),
+                online: true,
+                last_seen: online_player.last_seen,
+            });
+        }
+
+        inner
+            .ended
+            .iter()
+            .rev()
+            .find(|session| matches(&session.id, &session.name))
+            .map(|session| LastSeen {
+                id: session.id.clone(),
+                name: session.name.clone(),
+                online: false,
+                last_seen: session.end,
+            })
+    }
+}
+
+impl SessionsInner {
+    /// Ends the session of a player, if they are online.
+    fn end(&mut self, id: String, time: u64) {
+        if let Some(online_player) = self.online.remove(&id) {
+            self.ended.push(Session {
+                id,
+                name: online_player.name,
+                start: online_player.since,
+                end: time,
+            });
+        }
+    }
+
+    /// Ends every session at the time `end_time` picks and logs it.
+    fn end_all(&mut self, end_time: impl Fn(&OnlinePlayer) -> u64) -> Result<()> {
+        let mut gone: Vec<_> = self
+            .online
+            .iter()
+            .map(|(id, online_player)| (id.clone(), end_time(online_player)))
+            .collect();
+        gone.sort_by_key(|(_, time)| *time);
+
+        let mut events = vec![];
+        for (id, time) in gone {
+            self.end(id.clone(), time);
+            events.push(Event::Leave { time, id });
+        }
+        self.append(&events)
+    }
+
+    fn append(&self, events: &[Event]) -> Result<()> {
+        if events.is_empty() {
+            return Ok(());
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(&encode_events(events)?)?;
+        Ok(())
+    }
+
+    /// Drops sessions that ended before the retention and rewrites the log
+    /// from the sessions left, a temporary file first so a crash can't lose
+    /// it.
+    fn compact(&mut self, today: u64) -> Result<()> {
+        self.compacted_day = Some(today);
+        let cutoff = today.saturating_sub(self.config.retention_days) * DAY;
+        self.ended.retain(|session| session.end >= cutoff);
+
+        let mut events = vec![];
+        for session in &self.ended {
+            events.push(Event::Join {
+                time: session.start,
+                id: session.id.clone(),
+                name: session.name.clone(),
+            });
+            events.push(Event::Leave {
+                time: session.end,
+                id: session.id.clone(),
+            });
+        }
+        for (id, online_player) in &self.online {
+            events.push(Event::Join {
+                time: online_player.since,
+                id: id.clone(),
+                name: online_player.name.clone(),
+            });
+            events.push(Event::Seen {
+                time: online_player.last_seen,
+                ids: vec![id.clone()],
+            });
+        }
+        // stable, so a session's join stays before its leave
+        events.sort_by_key(Event::time);
+
+        let temp_path = self.path.with_extension("jsonl.tmp");
+        fs::write(&temp_path, encode_events(&events)?)?;
+        fs::rename(temp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+fn encode_events(events: &[Event]) -> Result<Vec<u8>> {
+    let mut data = vec![];
+    for event in events {
+        serde_json::to_writer(&mut data, event)?;
+        data.push(b'\n');
+    }
+    Ok(data)
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerPlaytime {
+    pub id: String,
+    pub name: String,
+    pub played_seconds: u64,
+    pub sessions: u32,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LastSeen {
+    pub id: String,
+    pub name: String,
+    pub online: bool,
+    pub last_seen: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TopPlayersQuery {
+    /// How many days back to look, a week by default.
+    pub days: Option<u64>,
+    pub limit: Option<usize>,
+}
+
+pub fn top_players_route(
+    sessions: Option<&Sessions>,
+    query: TopPlayersQuery,
+) -> Response<Vec<PlayerPlaytime>> {
+    let Some(sessions) = sessions else {
+        return Response::err(Error::Generic("history is not enabled".to_owned()));
+    };
+
+    let to = now();
+    let from = to.saturating_sub(query.days.unwrap_or(7) * DAY);
+    let limit = query.limit.unwrap_or(DEFAULT_TOP_LIMIT);
+    Response::ok(sessions.top_players(from, to, limit))
+}
+
+pub fn last_seen_route(sessions: Option<&Sessions>, player: &str) -> Response<LastSeen> {
+    let Some(sessions) = sessions else {
+        return Response::err(Error::Generic("history is not enabled".to_owned()));
+    };
+
+    match sessions.last_seen(player) {
+        Some(last_seen) => Response::ok(last_seen),
+        None => Response::err(Error::Generic(format!("player `{player}` was never seen"))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::report::mc::packet::status::PlayerSample;
+
+    use super::*;
+
+    const NOTCH: &str = "069a79f4-44e9-4726-a5be-fca90e38aaf5";
+    const JEB: &str = "853c80ef-3c37-49fd-aa49-938b674adae6";
+
+    fn open_sessions(name: &str) -> Sessions {
+        let dir = std::env::temp_dir().join(format!("mc-sessions-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        Sessions::open(dir, HistoryConfig::default()).unwrap()
+    }
+
+    fn reopen(sessions: &Sessions) -> Sessions {
+        let inner = sessions.inner.lock().unwrap();
+        let dir = inner.path.parent().unwrap();
+        Sessions::open(dir, inner.config).unwrap()
+    }
+
+    fn players(online: i32, sample: &[(&str, &str)]) -> Players {
+        Players {
+            max: 20,
+            online,
+            sample: sample
+                .iter()
+                .map(|(id, name)| PlayerSample {
+                    id: id.to_string(),
+                    name: name.to_string(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn complete_samples() {
+        let sessions = open_sessions("complete");
+        let notch = (NOTCH, "Notch");
+        let jeb = (JEB, "jeb_");
+        sessions.observe_at(1000, &players(1, &[notch])).unwrap();
+        sessions
+            .observe_at(1060, &players(2, &[notch, jeb]))
+            .unwrap();
+        sessions.observe_at(1120, &players(1, &[jeb])).unwrap();
+        sessions.observe_at(1180, &players(0, &[])).unwrap();
+
+        let top = sessions.top_players(0, 2000, 10);
+        assert_eq!(top.len(), 2);
+        assert_eq!(
+            (top[0].name.as_str(), top[0].played_seconds),
+            ("Notch", 120)
+        );
+        assert_eq!((top[1].name.as_str(), top[1].played_seconds), ("jeb_", 120));
+
+        let last_seen = sessions.last_seen("notch").unwrap();
+        assert_eq!(last_seen.id, NOTCH);
+        assert!(!last_seen.online);
+        assert_eq!(last_seen.last_seen, 1120);
+        assert_eq!(sessions.last_seen("Dinnerbone"), None);
+    }
+
+    #[test]
+    fn incomplete_samples_time_out() {
+        let sessions = open_sessions("incomplete");
+        sessions
+            .observe_at(1000, &players(15, &[(NOTCH, "Notch")]))
+            .unwrap();
+        // missing from a partial sample, but not for long enough
+        sessions
+            .observe_at(1060, &players(15, &[(JEB, "jeb_")]))
+            .unwrap();
+        assert!(sessions.last_seen(NOTCH).unwrap().online);
+
+        sessions
+            .observe_at(1000 + SESSION_TIMEOUT + 1, &players(15, &[(JEB, "jeb_")]))
+            .unwrap();
+        let last_seen = sessions.last_seen(NOTCH).unwrap();
+        assert!(!last_seen.online);
+        assert_eq!(last_seen.last_seen, 1000);
+
+        // a failed poll right after a status doesn't end anything
+        let last_status = 1000 + SESSION_TIMEOUT + 1;
+        sessions.poll_failed_at(last_status + 5).unwrap();
+        assert!(sessions.last_seen(JEB).unwrap().online);
+
+        sessions
+            .poll_failed_at(last_status + SESSION_TIMEOUT + 1)
+            .unwrap();
+        let last_seen = sessions.last_seen(JEB).unwrap();
+        assert!(!last_seen.online);
+        assert_eq!(last_seen.last_seen, last_status);
+    }
+
+    #[test]
+    fn top_players_are_clipped_to_range() {
+        let sessions = open_sessions("range");
+        sessions
+            .observe_at(1000, &players(1, &[(NOTCH, "Notch")]))
+            .unwrap();
+        sessions.observe_at(2000, &players(0, &[])).unwrap();
+        sessions
+            .observe_at(3000, &players(1, &[(NOTCH, "Notch")]))
+            .unwrap();
+        sessions
+            .observe_at(3500, &players(1, &[(NOTCH, "Notch")]))
+            .unwrap();
+
+        let top = sessions.top_players(1500, 3200, 10);
+        assert_eq!(top[0].played_seconds, 500 + 200);
+        assert_eq!(top[0].sessions, 2);
+
+        // a restart ends open sessions at the last heartbeat, and the
+        // reopened log adds up to the same sessions
+        let reopened = reopen(&sessions);
+        let last_seen = reopened.last_seen(NOTCH).unwrap();
+        assert!(!last_seen.online);
+        assert_eq!(last_seen.last_seen, 3500);
+        let top = reopened.top_players(1500, 3200, 10);
+        assert_eq!(top[0].played_seconds, 500 + 200);
+        assert_eq!(top[0].sessions, 2);
+
+        // and the leave is logged, so the session stays ended
+        let reopened = reopen(&reopened);
+        assert!(!reopened.last_seen(NOTCH).unwrap().online);
+        assert_eq!(
+            reopened.top_players(0, 4000, 10)[0].played_seconds,
+            1000 + 500
+        );
+    }
+
+    #[test]
+    fn heartbeats() {
+        let sessions = open_sessions("heartbeats");
+        let notch = players(1, &[(NOTCH, "Notch")]);
+        for time in (1000..=1300).step_by(30) {
+            sessions.observe_at(time, &notch).unwrap();
+        }
+
+        // only every HEARTBEAT_INTERVAL is logged, so a restart loses less
+        // than that
+        let events = read_events(&sessions.inner.lock().unwrap().path).unwrap();
+        let heartbeats = events
+            .iter()
+            .filter(|event| matches!(event, Event::Seen { .. }))
+            .count();
+        assert_eq!(heartbeats, 5);
+        let reopened = reopen(&sessions);
+        assert_eq!(reopened.last_seen(NOTCH).unwrap().last_seen, 1270);
+    }
+
+    #[test]
+    fn retention() {
+        let dir =
+            std::env::temp_dir().join(format!("mc-sessions-{}-retention", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let config = HistoryConfig {
+            retention_days: 2,
+            ..Default::default()
+        };
+        let sessions = Sessions::open(&dir, config).unwrap();
+        sessions
+            .observe_at(1000, &players(1, &[(NOTCH, "Notch")]))
+            .unwrap();
+        sessions.observe_at(1100, &players(0, &[])).unwrap();
+        sessions
+            .observe_at(DAY + 1000, &players(1, &[(JEB, "jeb_")]))
+            .unwrap();
+
+        // a new day drops the sessions past the retention
+        sessions
+            .observe_at(3 * DAY, &players(1, &[(JEB, "jeb_")]))
+            .unwrap();
+        assert_eq!(sessions.last_seen(NOTCH), None);
+        assert!(sessions.last_seen(JEB).unwrap().online);
+
+        // from the log too, which only keeps what is left
+        let reopened = reopen(&sessions);
+        assert_eq!(reopened.last_seen(NOTCH), None);
+        let last_seen = reopened.last_seen(JEB).unwrap();
+        assert_eq!(last_seen.last_seen, 3 * DAY);
+        let top = reopened.top_players(0, 4 * DAY, 10);
+        assert_eq!(top[0].played_seconds, 2 * DAY - 1000);
+    }
+}
@@ -0,0 +1,161 @@
+//! Variable length integers as used by the Minecraft protocol, see
+//! <https://wiki.vg/Protocol#VarInt_and_VarLong>. Unlike signed LEB128 the
+//! value is encoded as its unsigned two's complement, so negative numbers
+//! always take the maximum number of bytes.
+
+use bytes::BufMut;
+
+use super::bytes::DecodeError;
+
+const SEGMENT_BITS: u8 = 0x7F;
+const CONTINUE_BIT: u8 = 0x80;
+
+macro_rules! var_num {
+    ($(#[$attr:meta])* $name:ident, $signed:ty, $unsigned:ty, $max_size:expr) => {
+        $(#[$attr])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub struct $name(pub $signed);
+
+        $(#[$attr])*
+        impl $name {
+            pub const MAX_SIZE: usize = $max_size;
+
+            pub fn encode(self, buf: &mut impl BufMut) {
+                let mut value = self.0 as $unsigned;
+                loop {
+                    let byte = (value & SEGMENT_BITS as $unsigned) as u8;
+                    value >>= 7;
+                    if value == 0 {
+                        buf.put_u8(byte);
+                        return;
+                    }
+                    buf.put_u8(byte | CONTINUE_BIT);
+                }
+            }
+
+            pub fn encoded_len(self) -> usize {
+                let mut value = self.0 as $unsigned;
+                let mut len = 1;
+                while value >= CONTINUE_BIT as $unsigned {
+                    value >>= 7;
+                    len += 1;
+                }
+                len
+            }
+
+            /// Reads bytes from `next_byte` until the last byte of the number,
+            /// failing if it would be longer than [`Self::MAX_SIZE`].
+            pub fn decode<E>(mut next_byte: impl FnMut() -> Result<u8, E>) -> Result<Self, E>
+            where
+                E: From<DecodeError>,
+            {
+                let mut value: $unsigned = 0;
+                for position in 0..Self::MAX_SIZE {
+                    let byte = next_byte()?;
+                    value |= ((byte & SEGMENT_BITS) as $unsigned) << (7 * position);
+                    if byte & CONTINUE_BIT == 0 {
+                        return Ok(Self(value as $signed));
+                    }
+                }
+                Err(DecodeError::VarNumTooLong(Self::MAX_SIZE).into())
+            }
+        }
+    };
+}
+
+var_num!(VarInt, i32, u32, 5);
+var_num!(VarLong, i64, u64, 10);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::mc::bytes::Bytes;
+
+    const VARINT_VECTORS: &[(i32, &[u8])] = &[
+        (0, &[0x00]),
+        (1, &[0x01]),
+        (2, &[0x02]),
+        (127, &[0x7f]),
+        (128, &[0x80, 0x01]),
+        (255, &[0xff, 0x01]),
+        (25565, &[0xdd, 0xc7, 0x01]),
+        (2097151, &[0xff, 0xff, 0x7f]),
+        (2147483647, &[0xff, 0xff, 0xff, 0xff, 0x07]),
+        (-1, &[0xff, 0xff, 0xff, 0xff, 0x0f]),
+        (-2147483648, &[0x80, 0x80, 0x80, 0x80, 0x08]),
+    ];
+
+    const VARLONG_VECTORS: &[(i64, &[u8])] = &[
+        (0, &[0x00]),
+        (1, &[0x01]),
+        (2, &[0x02]),
+        (127, &[0x7f]),
+        (128, &[0x80, 0x01]),
+        (255, &[0xff, 0x01]),
+        (2147483647, &[0xff, 0xff, 0xff, 0xff, 0x07]),
+        (
+            9223372036854775807,
+            &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f],
+        ),
+        (
+            -1,
+            &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01],
+        ),
+        (
+            -2147483648,
+            &[0x80, 0x80, 0x80, 0x80, 0xf8, 0xff, 0xff, 0xff, 0xff, 0x01],
+        ),
+        (
+            -9223372036854775808,
+            &[0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x01],
+        ),
+    ];
+
+    #[test]
+    fn varint_reference_vectors() {
+        for &(value, expected) in VARINT_VECTORS {
+            let mut encoded = vec![];
+            VarInt(value).encode(&mut encoded);
+            assert_eq!(encoded, expected, "encoding {value}");
+            assert_eq!(VarInt(value).encoded_len(), expected.len());
+
+            let mut bytes = Bytes::from(expected);
+            let decoded = VarInt::decode(|| bytes.get_u8()).unwrap();
+            assert_eq!(decoded, VarInt(value), "decoding {expected:x?}");
+            assert_eq!(bytes.len(), 0);
+        }
+    }
+
+    #[test]
+    fn varlong_reference_vectors() {
+        for &(value, expected) in VARLONG_VECTORS {
+            let mut encoded = vec![];
+            VarLong(value).encode(&mut encoded);
+            assert_eq!(encoded, expected, "encoding {value}");
+            assert_eq!(VarLong(value).encoded_len(), expected.len());
+
+            let mut bytes = Bytes::from(expected);
+            let decoded = VarLong::decode(|| bytes.get_u8()).unwrap();
+            assert_eq!(decoded, VarLong(value), "decoding {expected:x?}");
+            assert_eq!(bytes.len(), 0);
+        }
+    }
+
+    #[test]
+    fn rejects_too_long() {
+        let mut bytes = Bytes::from(&[0xff; 6][..]);
+        let varint = VarInt::decode(|| bytes.get_u8());
+        assert!(matches!(varint, Err(DecodeError::VarNumTooLong(5))));
+
+        let mut bytes = Bytes::from(&[0xff; 11][..]);
+        let varlong = VarLong::decode(|| bytes.get_u8());
+        assert!(matches!(varlong, Err(DecodeError::VarNumTooLong(10))));
+    }
+
+    #[test]
+    fn rejects_truncated() {
+        let mut bytes = Bytes::from(&[0x80, 0x80][..]);
+        let varint = VarInt::decode(|| bytes.get_u8());
+        assert!(matches!(varint, Err(DecodeError::UnexpectedEof { .. })));
+    }
+}
@@ -1 +1 @@
-pub mod mc;
\ No newline at end of file
+pub mod mc;
@@ -4,11 +4,13 @@ use lettre::{
 };
 use serde::Deserialize;
 
-use crate::{error::Error, response::Response};
+use crate::{error::Error, metrics::METRICS, response::Response};
 
 pub fn send_email(data: SendEmailData) -> Response<()> {
     tracing::debug!("sending email: {:?}", data);
-    match send_email_impl(data) {
+    let result = send_email_impl(data);
+    METRICS.record_email(&result);
+    match result {
         Ok(_) => Response::ok(()),
         Err(error) => Response::err(error),
     }
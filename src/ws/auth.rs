@@ -0,0 +1,114 @@
+//! Which channels a connection may subscribe to, depending on the token it
+//! authenticated with.
+
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+};
+
+use crate::error::Error;
+
+use super::management::Channel;
+
+impl FromStr for Channel {
+    type Err = Error;
+
+    fn from_str(channel: &str) -> Result<Self, Self::Err> {
+        match channel {
+            "minecraft" => Ok(Channel::Minecraft),
+            "bedrock" => Ok(Channel::Bedrock),
+            _ => Err(Error::Generic(format!("unknown channel `{channel}`"))),
+        }
+    }
+}
+
+/// Parses a comma separated list of channels, `*` stands for all of them.
+fn parse_channels(channels: &str) -> Result<HashSet<Channel>, Error> {
+    let mut parsed = HashSet::new();
+    for channel in channels.split(',').map(str::trim).filter(|c| !c.is_empty()) {
+        if channel == "*" {
+            parsed.extend(Channel::ALL);
+        } else {
+            parsed.insert(channel.parse()?);
+        }
+    }
+    Ok(parsed)
+}
+
+#[derive(Debug, Clone)]
+pub struct AuthConfig {
+    /// Channels anyone may subscribe to, without a token.
+    pub public_channels: HashSet<Channel>,
+    /// Channels each token unlocks on top of the public ones.
+    pub tokens: HashMap<String, HashSet<Channel>>,
+}
+
+/// Every channel is public, as before tokens existed.
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            public_channels: Channel::ALL.iter().copied().collect(),
+            tokens: HashMap::new(),
+        }
+    }
+}
+
+impl AuthConfig {
+    /// Reads `WS_PUBLIC_CHANNELS`, e.g. `minecraft,bedrock`, and `WS_TOKENS`,
+    /// e.g. `token1=minecraft,bedrock;token2=*`.
+    pub fn from_env() -> Result<Self, Error> {
+        let mut config = Self::default();
+        if let Ok(channels) = std::env::var("WS_PUBLIC_CHANNELS") {
+            config.public_channels = parse_channels(&channels)?;
+        }
+        if let Ok(tokens) = std::env::var("WS_TOKENS") {
+            for entry in tokens.split(';').filter(|entry| !entry.trim().is_empty()) {
+                let (token, channels) = entry
+                    .split_once('=')
+                    .ok_or_else(|| Error::Generic("`WS_TOKENS` entry without `=`".to_owned()))?;
+                config
+                    .tokens
+                    .insert(token.trim().to_owned(), parse_channels(channels)?);
+            }
+        }
+        Ok(config)
+    }
+
+    /// Channels a connection may use, `None` when the token is unknown.
+    pub fn channels_for(&self, token: Option<&str>) -> Option<HashSet<Channel>> {
+        let mut channels = self.public_channels.clone();
+        if let Some(token) = token {
+            channels.extend(self.tokens.get(token)?);
+        }
+        Some(channels)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_channels() {
+        let config = AuthConfig {
+            public_channels: parse_channels("minecraft").unwrap(),
+            tokens: HashMap::from([
+                ("admin".to_owned(), parse_channels("*").unwrap()),
+                ("friends".to_owned(), parse_channels("bedrock").unwrap()),
+            ]),
+        };
+
+        assert_eq!(
+            config.channels_for(None),
+            Some(HashSet::from([Channel::Minecraft]))
+        );
+        assert_eq!(
+            config.channels_for(Some("friends")),
+            Some(HashSet::from([Channel::Minecraft, Channel::Bedrock]))
+        );
+        assert_eq!(config.channels_for(Some("admin")).unwrap().len(), 2);
+        assert_eq!(config.channels_for(Some("guess")), None);
+
+        assert!(parse_channels("minecraft, steam").is_err());
+    }
+}
@@ -0,0 +1,250 @@
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use crate::{error::Error, metrics::METRICS, report::mc::bedrock::BedrockStatus};
+use serde::Serialize;
+
+use super::{
+    minecraft::{
+        is_significant_latency_change, LatencyMessage, OnlinePlayersMessage, StatusMessage,
+    },
+    poll::Pollers,
+    supervisor::{supervise, RESTART_DELAY},
+    topic::{topic, Publisher, Topic},
+};
+
+#[derive(Debug, Clone)]
+pub struct BedrockJobConfig {
+    /// Server to poll, read from the environment on every poll when `None`.
+    /// Without one here or in the environment the job doesn't poll.
+    pub server_addr: Option<SocketAddr>,
+    /// How often to ping the server, `None` disables polling.
+    pub status_interval: Option<Duration>,
+}
+
+impl Default for BedrockJobConfig {
+    fn default() -> Self {
+        Self {
+            server_addr: None,
+            status_interval: Some(Duration::from_secs(10)),
+        }
+    }
+}
+
+impl BedrockJobConfig {
+    fn is_configured(&self) -> bool {
+        self.server_addr.is_some() || crate::report::mc::bedrock::is_configured()
+    }
+
+    fn server_addr(&self) -> Result<SocketAddr, Error> {
+        match self.server_addr {
+            Some(addr) => Ok(addr),
+            None => crate::report::mc::bedrock::bedrock_addr(),
+        }
+    }
+}
+
+/// Polls a Bedrock server with unconnected pings, which carry the whole
+/// status, and pushes the changes to subscribers.
+pub struct BedrockJob {
+    topic: Topic,
+}
+
+impl BedrockJob {
+    pub fn new(config: BedrockJobConfig) -> Self {
+        let (publisher, topic) = topic(BedrockServerStatus::default().to_update_message());
+        let publisher = Arc::new(publisher);
+        tokio::spawn(supervise("bedrock", RESTART_DELAY, move || {
+            BedrockJob::service(config.clone(), publisher.clone())
+        }));
+
+        BedrockJob { topic }
+    }
+
+    pub(super) fn topic(&self) -> Topic {
+        self.topic.clone()
+    }
+
+    async fn service(config: BedrockJobConfig, publisher: Arc<Publisher>) {
+        let mut current_server_status = BedrockServerStatus::default();
+
+        let mut pollers = Pollers::new("bedrock");
+        match config.status_interval {
+            Some(status_interval) if config.is_configured() => {
+                pollers.spawn("status", status_interval, move || {
+                    crate::report::mc::bedrock::status_at(config.server_addr()?)
+                });
+            }
+            Some(_) => tracing::info!("no bedrock server configured, not polling"),
+            None => {}
+        }
+
+        while let Some(message) = pollers.next().await {
+            let update_messages = current_server_status.apply_response(message);
+            current_server_status.record_gauges();
+            publisher.publish(update_messages, || {
+                current_server_status.to_update_message()
+            });
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BedrockServerStatus {
+    online: bool,
+    reason_for_offline: Option<String>,
+    edition: String,
+    motd: String,
+    sub_motd: Option<String>,
+    players: Players,
+    version: String,
+    protocol: i32,
+    game_mode: Option<String>,
+    latency_ms: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct Players {
+    online: i32,
+    max: i32,
+}
+
+impl Default for BedrockServerStatus {
+    fn default() -> Self {
+        Self {
+            online: false,
+            reason_for_offline: Some("server status uninitialized".to_owned()),
+            edition: String::new(),
+            motd: String::new(),
+            sub_motd: None,
+            players: Players { online: 0, max: 0 },
+            version: "0.0.0".to_owned(),
+            protocol: 0,
+            game_mode: None,
+            latency_ms: None,
+        }
+    }
+}
+
+impl BedrockServerStatus {
+    fn to_update_message(&self) -> UpdateMessage {
+        UpdateMessage::Initial(self.clone())
+    }
+
+    fn to_status_message(&self) -> UpdateMessage {
+        UpdateMessage::Status(StatusMessage {
+            online: self.online,
+            reason_for_offline: self.reason_for_offline.clone(),
+        })
+    }
+
+    fn record_gauges(&self) {
+        METRICS
+            .server_online
+            .with_label_values(&["bedrock"])
+            .set(self.online.into());
+        METRICS
+            .players_online
+            .with_label_values(&["bedrock"])
+            .set(self.players.online.into());
+        METRICS
+            .players_max
+            .with_label_values(&["bedrock"])
+            .set(self.players.max.into());
+    }
+
+    fn apply_response(&mut self, response: Result<BedrockStatus, Error>) -> Vec<UpdateMessage> {
+        let status = match response {
+            Ok(status) => status,
+            Err(err) => {
+                let changed = self.online;
+                self.online = false;
+                self.reason_for_offline = Some(err.to_string());
+                self.latency_ms = None;
+
+                return if changed {
+                    vec![self.to_status_message()]
+                } else {
+                    vec![]
+                };
+            }
+        };
+
+        let previous = std::mem::replace(
+            self,
+            BedrockServerStatus {
+                online: true,
+                reason_for_offline: None,
+                edition: status.edition,
+                motd: status.motd,
+                sub_motd: status.sub_motd,
+                players: Players {
+                    online: status.players_online,
+                    max: status.players_max,
+                },
+                version: status.version,
+                protocol: status.protocol,
+                game_mode: status.game_mode,
+                latency_ms: None,
+            },
+        );
+
+        let mut update_messages = vec![];
+        if !previous.online {
+            update_messages.push(self.to_status_message());
+        }
+        if previous.players.online != self.players.online {
+            update_messages.push(UpdateMessage::OnlinePlayers(OnlinePlayersMessage {
+                players_online: self.players.online,
+            }));
+        }
+        if (&previous.version, previous.protocol) != (&self.version, self.protocol) {
+            update_messages.push(UpdateMessage::Version(VersionMessage {
+                version: self.version.clone(),
+                protocol: self.protocol,
+            }));
+        }
+        if (&previous.motd, &previous.sub_motd) != (&self.motd, &self.sub_motd) {
+            update_messages.push(UpdateMessage::Motd(MotdMessage {
+                motd: self.motd.clone(),
+                sub_motd: self.sub_motd.clone(),
+            }));
+        }
+
+        // compared against the last reported latency, like the Java job does
+        if is_significant_latency_change(previous.latency_ms, status.latency.ping_ms) {
+            self.latency_ms = status.latency.ping_ms;
+            update_messages.push(UpdateMessage::Latency(LatencyMessage {
+                latency_ms: self.latency_ms,
+            }));
+        } else {
+            self.latency_ms = previous.latency_ms;
+        }
+
+        update_messages
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum UpdateMessage {
+    Initial(BedrockServerStatus),
+    Status(StatusMessage),
+    OnlinePlayers(OnlinePlayersMessage),
+    Version(VersionMessage),
+    Motd(MotdMessage),
+    Latency(LatencyMessage),
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct VersionMessage {
+    version: String,
+    protocol: i32,
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MotdMessage {
+    motd: String,
+    sub_motd: Option<String>,
+}
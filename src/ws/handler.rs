@@ -1,26 +1,105 @@
-use std::{net::SocketAddr, ops::ControlFlow, time::Duration};
+use std::{
+    net::SocketAddr,
+    ops::ControlFlow,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
-use crate::ws::management::CommandResponse;
+use crate::{logging::request::RequestId, ws::management::CommandResponse};
 
-use super::management::{Manager, SubscriptionResponse, UniqueId};
+use super::{
+    limits::{Connections, RateLimit, WsLimits},
+    management::{Channel, Manager, SubscriptionResponse, UniqueId},
+};
 use axum::{
     extract::{
-        ws::{Message, WebSocket},
-        ConnectInfo, State, WebSocketUpgrade,
+        ws::{close_code, CloseFrame, Message, WebSocket},
+        ConnectInfo, Query, State, WebSocketUpgrade,
     },
-    headers,
+    headers::{self, authorization::Bearer, Authorization},
     response::IntoResponse,
-    TypedHeader,
+    Extension, TypedHeader,
 };
-use futures_util::{SinkExt, StreamExt};
-use serde::Deserialize;
-use tokio::sync::mpsc;
+use futures_util::{stream, SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::{
+    sync::mpsc::{self, error::TrySendError},
+    task::JoinSet,
+    time::Instant,
+};
+use tracing::Instrument;
+
+/// Version of the frames below, announced in the `hello` frame and bumped on
+/// incompatible changes.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Frames the server sends besides the channel updates.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum ServerFrame {
+    Hello {
+        version: u32,
+        /// Channels the connection may subscribe to.
+        channels: Vec<Channel>,
+    },
+    Ack {
+        id: Option<Value>,
+        command: Command,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        channel: Option<Channel>,
+        /// Channels the connection may use after authenticating.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        channels: Option<Vec<Channel>>,
+    },
+    Error {
+        id: Option<Value>,
+        error: String,
+    },
+}
+
+impl From<ServerFrame> for Message {
+    fn from(frame: ServerFrame) -> Self {
+        Message::Text(serde_json::to_string(&frame).expect("should always parse successfully"))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ConnectParams {
+    token: Option<String>,
+}
+
+/// State of the `/ws` route.
+#[derive(Debug, Clone)]
+pub struct WsState {
+    manager: Manager,
+    limits: Arc<WsLimits>,
+    connections: Connections,
+}
 
+impl WsState {
+    pub fn new(manager: Manager, limits: WsLimits) -> Self {
+        Self {
+            manager,
+            limits: Arc::new(limits),
+            connections: Connections::default(),
+        }
+    }
+}
+
+/// How long the close handshake may take once a side closed the connection.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Connections authenticate with a `token` query parameter, a bearer token or
+/// an `authenticate` command, and start with the public channels otherwise.
 pub async fn ws_handler(
     ws: WebSocketUpgrade,
-    State(manager): State<Manager>,
+    State(state): State<WsState>,
     user_agent: Option<TypedHeader<headers::UserAgent>>,
+    authorization: Option<TypedHeader<Authorization<Bearer>>>,
+    Query(params): Query<ConnectParams>,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    request_id: Option<Extension<RequestId>>,
 ) -> impl IntoResponse {
     let user_agent = if let Some(TypedHeader(user_agent)) = user_agent {
         user_agent.to_string()
@@ -30,13 +109,54 @@ pub async fn ws_handler(
     tracing::debug!("`{user_agent}` at {addr} connected.");
     // finalize the upgrade process by returning upgrade callback.
     // we can customize the callback by sending additional info such as address.
-    ws.on_upgrade(move |socket| handle_socket(socket, addr, manager))
+    let token = params
+        .token
+        .or_else(|| authorization.map(|TypedHeader(auth)| auth.token().to_owned()));
+    let connection = state
+        .connections
+        .acquire(addr.ip(), state.limits.max_connections_per_ip);
+    // outlives the request, so it gets its own span carrying the request id
+    // and, once registered, the connection id
+    let span = tracing::info_span!(
+        "ws",
+        request_id = request_id
+            .as_ref()
+            .map_or("", |Extension(id)| id.0.as_str()),
+        connection = tracing::field::Empty,
+    );
+    ws.on_upgrade(move |mut socket| {
+        async move {
+            let Some(_connection) = connection else {
+                tracing::debug!("too many connections from {}", addr.ip());
+                let close = close_frame(close_code::AGAIN, "too many connections");
+                let _ = socket.send(close).await;
+                return;
+            };
+            handle_socket(socket, addr, state.manager, &state.limits, token).await
+        }
+        .instrument(span)
+    })
 }
 
-async fn handle_socket(mut socket: WebSocket, who: SocketAddr, manager: Manager) {
-    if ping_websocket(&mut socket, &who).await.is_break() {
+fn close_frame(code: u16, reason: &'static str) -> Message {
+    Message::Close(Some(CloseFrame {
+        code,
+        reason: reason.into(),
+    }))
+}
+
+async fn handle_socket(
+    mut socket: WebSocket,
+    who: SocketAddr,
+    manager: Manager,
+    limits: &WsLimits,
+    token: Option<String>,
+) {
+    let ControlFlow::Continue(first_message) =
+        ping_websocket(&mut socket, &who, limits.idle_timeout).await
+    else {
         return;
-    }
+    };
 
     let (sub_tx, sub_rx) = mpsc::channel(16);
     if let Some(response) = manager
@@ -47,8 +167,27 @@ async fn handle_socket(mut socket: WebSocket, who: SocketAddr, manager: Manager)
             crate::ws::management::CommandResponse::Registered(id) => id,
             _ => return,
         };
+        tracing::Span::current().record("connection", tracing::field::debug(id));
 
-        handle_communication_with_manager(socket, id, sub_rx, &manager).await;
+        if let ControlFlow::Continue(channels) =
+            authenticate(&mut socket, id, &manager, token).await
+        {
+            let hello = ServerFrame::Hello {
+                version: PROTOCOL_VERSION,
+                channels,
+            };
+            if socket.send(hello.into()).await.is_ok() {
+                handle_communication_with_manager(
+                    socket,
+                    id,
+                    sub_rx,
+                    &manager,
+                    first_message,
+                    limits,
+                )
+                .await;
+            }
+        }
 
         manager
             .send_command(super::management::Command::Unregister(id))
@@ -58,130 +197,313 @@ async fn handle_socket(mut socket: WebSocket, who: SocketAddr, manager: Manager)
     tracing::debug!("websocket context {who} destroyed");
 }
 
+/// Closes the socket when the token given while connecting is unknown, and
+/// continues with the channels the connection may use otherwise.
+async fn authenticate(
+    socket: &mut WebSocket,
+    id: UniqueId,
+    manager: &Manager,
+    token: Option<String>,
+) -> ControlFlow<(), Vec<Channel>> {
+    let command = match token {
+        Some(token) => super::management::Command::Authenticate(id, token),
+        None => super::management::Command::Channels(id),
+    };
+
+    match manager.send_command(command).await {
+        Some(CommandResponse::Authenticated(channels) | CommandResponse::Channels(channels)) => {
+            ControlFlow::Continue(channels)
+        }
+        _ => {
+            let error = ServerFrame::Error {
+                id: None,
+                error: "invalid token".to_owned(),
+            };
+            // the client may already be gone, it's closed either way
+            let _ = socket.send(error.into()).await;
+            let _ = socket
+                .send(close_frame(close_code::POLICY, "invalid token"))
+                .await;
+            ControlFlow::Break(())
+        }
+    }
+}
+
+/// Messages on their way to the client. The queue is bounded, a client too
+/// slow to keep up is disconnected rather than buffered for.
+#[derive(Debug, Clone)]
+struct Outbox {
+    queue: mpsc::Sender<Message>,
+    close: mpsc::Sender<Message>,
+}
+
+impl Outbox {
+    fn send(&self, message: Message) -> ControlFlow<()> {
+        match self.queue.try_send(message) {
+            Ok(()) => ControlFlow::Continue(()),
+            Err(TrySendError::Full(_)) => {
+                self.close(close_code::AGAIN, "send queue full");
+                ControlFlow::Break(())
+            }
+            Err(TrySendError::Closed(_)) => ControlFlow::Break(()),
+        }
+    }
+
+    /// Sends a close frame ahead of the queued messages, the first one wins.
+    fn close(&self, code: u16, reason: &'static str) {
+        tracing::debug!("closing websocket: {reason}");
+        let _ = self.close.try_send(close_frame(code, reason));
+    }
+}
+
 async fn handle_communication_with_manager(
     socket: WebSocket,
     id: UniqueId,
     mut sub_rx: mpsc::Receiver<SubscriptionResponse>,
     manager: &Manager,
+    first_message: Option<Message>,
+    limits: &WsLimits,
 ) {
-    let (mut tx, mut rx) = socket.split();
+    let (mut tx, rx) = socket.split();
 
-    let (send_to_sink, mut recv_to_sink) = mpsc::channel(16);
+    let (queue, mut queued) = mpsc::channel(limits.send_queue);
+    let (close, mut close_requested) = mpsc::channel(1);
+    let outbox = Outbox { queue, close };
 
-    let mut send_task = tokio::spawn(async move {
-        while let Some(msg) = recv_to_sink.recv().await {
-            if tx.send(msg).await.is_err() {
-                break;
+    let mut send_task = tokio::spawn(
+        async move {
+            loop {
+                let msg = tokio::select! {
+                    biased;
+                    Some(close) = close_requested.recv() => {
+                        let _ = tx.send(close).await;
+                        break;
+                    }
+                    Some(msg) = queued.recv() => msg,
+                    else => break,
+                };
+                if tx.send(msg).await.is_err() {
+                    break;
+                }
             }
         }
-    });
+        .in_current_span(),
+    );
 
-    let manager_sender = send_to_sink.clone();
-    tokio::spawn(async move {
-        while let Some(response) = sub_rx.recv().await {
-            if let Err(error) = manager_sender
-                .send(Message::Text(
+    // tasks that only feed the outbox, dropped with the connection
+    let mut feeders = JoinSet::new();
+
+    let manager_outbox = outbox.clone();
+    feeders.spawn(
+        async move {
+            while let Some(response) = sub_rx.recv().await {
+                let msg = Message::Text(
                     serde_json::to_string(&response).expect("should always parse successfully"),
-                ))
-                .await
-            {
-                tracing::error!("failed sending info to websocket: {error}");
-                break;
+                );
+                if manager_outbox.send(msg).is_break() {
+                    return;
+                }
             }
+            manager_outbox.close(close_code::AWAY, "server going away");
         }
-    });
-
-    let ping_sender = send_to_sink.clone();
-    tokio::spawn(async move {
-        loop {
-            if ping_sender
-                .send(Message::Ping(vec![1, 2, 3]))
-                .await
-                .is_err()
-            {
-                break;
+        .in_current_span(),
+    );
+
+    let last_seen = Arc::new(Mutex::new(Instant::now()));
+    let ping_outbox = outbox.clone();
+    let ping_last_seen = last_seen.clone();
+    let (ping_interval, idle_timeout) = (limits.ping_interval, limits.idle_timeout);
+    feeders.spawn(
+        async move {
+            let mut pings = tokio::time::interval_at(Instant::now() + ping_interval, ping_interval);
+            loop {
+                pings.tick().await;
+                let idle = ping_last_seen
+                    .lock()
+                    .expect("last seen lock poisoned")
+                    .elapsed();
+                if idle >= idle_timeout {
+                    ping_outbox.close(close_code::AWAY, "idle timeout");
+                    return;
+                }
+                if ping_outbox.send(Message::Ping(vec![1, 2, 3])).is_break() {
+                    return;
+                }
             }
-            tokio::time::sleep(Duration::from_secs(30)).await;
         }
-    });
+        .in_current_span(),
+    );
 
     let mgr = manager.clone();
-    let mut recv_task = tokio::spawn(async move {
-        while let Some(msg_res) = rx.next().await {
-            match msg_res {
-                Ok(msg) => {
-                    if let ControlFlow::Continue(command) = process_message(msg) {
-                        if let Some(CommandResponse::Subscribed) =
-                            mgr.send_command(command.with_unique_id(id)).await
-                        {
-                            match command {
-                                WebsocketCommand::Known {
-                                    command: _,
-                                    channel,
-                                } => {
-                                    tracing::debug!(
-                                        "trying to assign a receiver to {channel:?} channel"
-                                    );
-                                }
-                                WebsocketCommand::Unknown => {}
-                            }
+    let reply_outbox = outbox;
+    let mut rate = RateLimit::new(limits.max_messages_per_second, Instant::now().into_std());
+    // the message answering the initial ping may already be a command
+    let mut rx = stream::iter(first_message.map(Ok)).chain(rx);
+    let mut recv_task = tokio::spawn(
+        async move {
+            while let Some(msg_res) = rx.next().await {
+                match msg_res {
+                    Ok(msg) => {
+                        let now = Instant::now();
+                        *last_seen.lock().expect("last seen lock poisoned") = now;
+                        if !rate.allow(now.into_std()) {
+                            reply_outbox.close(close_code::POLICY, "rate limit exceeded");
+                            break;
+                        }
+
+                        let ControlFlow::Continue(request) = process_message(msg) else {
+                            break;
+                        };
+                        let Some(request) = request else {
+                            continue;
+                        };
+
+                        let reply = handle_request(request, id, &mgr).await;
+                        if reply_outbox.send(reply.into()).is_break() {
+                            break;
                         }
-                    } else {
-                        break;
                     }
+                    Err(error) => tracing::error!("websocket receive error: {error}"),
                 }
-                Err(error) => tracing::error!("websocket receive error: {error}"),
             }
         }
-    });
+        .in_current_span(),
+    );
 
-    tokio::select! {
-        _ = (&mut send_task) => recv_task.abort(),
-        _ = (&mut recv_task) => send_task.abort(),
+    let closed_by_server = tokio::select! {
+        _ = (&mut send_task) => true,
+        _ = (&mut recv_task) => false,
+    };
+    drop(feeders);
+    // waits for the client to answer our close frame, or for a close frame
+    // requested on the way out to be sent
+    let mut closing = if closed_by_server {
+        recv_task
+    } else {
+        send_task
+    };
+    if tokio::time::timeout(CLOSE_TIMEOUT, &mut closing)
+        .await
+        .is_err()
+    {
+        closing.abort();
     }
 }
 
-#[derive(Debug, Deserialize, Clone, Copy)]
-#[serde(untagged)]
+async fn handle_request(request: Request, id: UniqueId, manager: &Manager) -> ServerFrame {
+    let command = match request.command {
+        Ok(command) => command,
+        Err(error) => {
+            return ServerFrame::Error {
+                id: request.id,
+                error,
+            }
+        }
+    };
+
+    let error = |error: &str| ServerFrame::Error {
+        id: request.id.clone(),
+        error: error.to_owned(),
+    };
+    match manager.send_command(command.with_unique_id(id)).await {
+        Some(CommandResponse::Subscribed | CommandResponse::Unsubscribed) => {
+            tracing::debug!("{command:?} handled");
+            ServerFrame::Ack {
+                id: request.id,
+                command: command.command(),
+                channel: command.channel(),
+                channels: None,
+            }
+        }
+        Some(CommandResponse::Authenticated(channels)) => ServerFrame::Ack {
+            id: request.id,
+            command: command.command(),
+            channel: None,
+            channels: Some(channels),
+        },
+        Some(CommandResponse::Unauthorized) => error("invalid token"),
+        Some(CommandResponse::Forbidden) => error("not allowed to subscribe to this channel"),
+        _ => error("the command could not be handled"),
+    }
+}
+
+/// A client message, `id` is echoed back in the `ack` or `error` frame
+/// answering it.
+struct Request {
+    id: Option<Value>,
+    command: Result<WebsocketCommand, String>,
+}
+
+impl Request {
+    fn parse(text: &str) -> Self {
+        let value: Value = match serde_json::from_str(text) {
+            Ok(value) => value,
+            Err(error) => {
+                return Self {
+                    id: None,
+                    command: Err(format!("invalid JSON: {error}")),
+                }
+            }
+        };
+
+        Self {
+            id: value.get("id").cloned(),
+            command: serde_json::from_value(value).map_err(|error| error.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "command", rename_all = "lowercase")]
 enum WebsocketCommand {
-    Known { command: Command, channel: Channel },
-    Unknown,
+    Subscribe { channel: Channel },
+    Unsubscribe { channel: Channel },
+    Authenticate { token: String },
 }
 
 impl WebsocketCommand {
     fn with_unique_id(&self, id: UniqueId) -> super::management::Command {
-        match *self {
-            WebsocketCommand::Known { command, channel } => match command {
-                Command::Subscribe => super::management::Command::Subscribe(id, channel.into()),
-                Command::Unsubscribe => super::management::Command::Unsubscribe(id, channel.into()),
-            },
-            WebsocketCommand::Unknown => super::management::Command::None,
+        match self {
+            WebsocketCommand::Subscribe { channel } => {
+                super::management::Command::Subscribe(id, *channel)
+            }
+            WebsocketCommand::Unsubscribe { channel } => {
+                super::management::Command::Unsubscribe(id, *channel)
+            }
+            WebsocketCommand::Authenticate { token } => {
+                super::management::Command::Authenticate(id, token.clone())
+            }
+        }
+    }
+
+    fn command(&self) -> Command {
+        match self {
+            WebsocketCommand::Subscribe { .. } => Command::Subscribe,
+            WebsocketCommand::Unsubscribe { .. } => Command::Unsubscribe,
+            WebsocketCommand::Authenticate { .. } => Command::Authenticate,
+        }
+    }
+
+    fn channel(&self) -> Option<Channel> {
+        match self {
+            WebsocketCommand::Subscribe { channel } | WebsocketCommand::Unsubscribe { channel } => {
+                Some(*channel)
+            }
+            WebsocketCommand::Authenticate { .. } => None,
         }
     }
 }
 
-#[derive(Debug, Deserialize, Clone, Copy)]
+#[derive(Debug, Serialize, Clone, Copy)]
 #[serde(rename_all = "lowercase")]
 enum Command {
     Subscribe,
     Unsubscribe,
+    Authenticate,
 }
 
-#[derive(Debug, Deserialize, PartialEq, Eq, Hash, Clone, Copy)]
-#[serde(rename_all = "lowercase")]
-enum Channel {
-    Minecraft,
-}
-
-impl From<Channel> for super::management::Channel {
-    fn from(value: Channel) -> Self {
-        match value {
-            Channel::Minecraft => Self::Minecraft,
-        }
-    }
-}
-
-fn process_message(msg: Message) -> ControlFlow<(), WebsocketCommand> {
+/// Text messages are requests, control frames need no answer.
+fn process_message(msg: Message) -> ControlFlow<(), Option<Request>> {
     match msg {
         Message::Close(c) => {
             if let Some(cf) = c {
@@ -195,21 +517,22 @@ fn process_message(msg: Message) -> ControlFlow<(), WebsocketCommand> {
             }
             ControlFlow::Break(())
         }
-        Message::Text(text) => {
-            let command = match serde_json::from_str::<WebsocketCommand>(&text) {
-                Ok(cmd) => cmd,
-                Err(_) => WebsocketCommand::Unknown,
-            };
-            ControlFlow::Continue(command)
-        }
-        _ => ControlFlow::Continue(WebsocketCommand::Unknown),
+        Message::Text(text) => ControlFlow::Continue(Some(Request::parse(&text))),
+        Message::Binary(_) => ControlFlow::Continue(Some(Request {
+            id: None,
+            command: Err("binary messages are not supported".to_owned()),
+        })),
+        Message::Ping(_) | Message::Pong(_) => ControlFlow::Continue(None),
     }
 }
 
+/// Returns the message the client answered with, so that it can be handled
+/// once the socket is registered.
 async fn ping_websocket(
     socket: &mut WebSocket,
     who: &SocketAddr,
-) -> ControlFlow<(), WebsocketCommand> {
+    timeout: Duration,
+) -> ControlFlow<(), Option<Message>> {
     if socket.send(Message::Ping(vec![1, 2, 3])).await.is_ok() {
         tracing::debug!("Pinged {}...", who);
     } else {
@@ -217,10 +540,73 @@ async fn ping_websocket(
         return ControlFlow::Break(());
     }
 
-    if let Some(Ok(msg)) = socket.recv().await {
-        process_message(msg)
-    } else {
-        tracing::warn!("client {who} abruptly disconnected");
-        ControlFlow::Break(())
+    match tokio::time::timeout(timeout, socket.recv()).await {
+        Ok(Some(Ok(Message::Close(_)))) => ControlFlow::Break(()),
+        Ok(Some(Ok(msg))) => ControlFlow::Continue(Some(msg)),
+        Err(_) => {
+            tracing::debug!("client {who} did not answer the first ping");
+            ControlFlow::Break(())
+        }
+        _ => {
+            tracing::warn!("client {who} abruptly disconnected");
+            ControlFlow::Break(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests() {
+        let request = Request::parse(r#"{"id": 7, "command": "subscribe", "channel": "bedrock"}"#);
+        assert_eq!(request.id, Some(Value::from(7)));
+        let command = request.command.unwrap();
+        assert!(matches!(command.command(), Command::Subscribe));
+        assert_eq!(command.channel(), Some(Channel::Bedrock));
+
+        let request = Request::parse(r#"{"command": "authenticate", "token": "secret"}"#);
+        assert_eq!(request.id, None);
+        assert!(matches!(
+            request.command.unwrap(),
+            WebsocketCommand::Authenticate { token } if token == "secret"
+        ));
+
+        let request = Request::parse(r#"{"id": "a", "command": "subscribe", "channel": "steam"}"#);
+        assert_eq!(request.id, Some(Value::from("a")));
+        assert!(request
+            .command
+            .unwrap_err()
+            .contains("unknown variant `steam`"));
+
+        let request = Request::parse("{not json");
+        assert_eq!(request.id, None);
+        assert!(request.command.unwrap_err().starts_with("invalid JSON"));
+    }
+
+    #[test]
+    fn frames() {
+        let hello = serde_json::to_value(ServerFrame::Hello {
+            version: PROTOCOL_VERSION,
+            channels: vec![Channel::Minecraft],
+        })
+        .unwrap();
+        assert_eq!(
+            hello,
+            serde_json::json!({"type": "hello", "version": 1, "channels": ["minecraft"]})
+        );
+
+        let ack = serde_json::to_value(ServerFrame::Ack {
+            id: Some(Value::from(7)),
+            command: Command::Subscribe,
+            channel: Some(Channel::Minecraft),
+            channels: None,
+        })
+        .unwrap();
+        assert_eq!(
+            ack,
+            serde_json::json!({"type": "ack", "id": 7, "command": "subscribe", "channel": "minecraft"})
+        );
     }
 }
@@ -0,0 +1,182 @@
+//! Limits keeping dead or misbehaving WebSocket clients from holding on to
+//! server resources.
+
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{error::Error, metrics::METRICS};
+
+#[derive(Debug, Clone)]
+pub struct WsLimits {
+    /// How often clients are pinged.
+    pub ping_interval: Duration,
+    /// How long a client may stay silent, pongs included, before it is
+    /// disconnected.
+    pub idle_timeout: Duration,
+    /// Messages waiting to be sent to a client before it is considered too
+    /// slow and disconnected.
+    pub send_queue: usize,
+    /// Messages a client may send per second.
+    pub max_messages_per_second: u32,
+    /// Connections open at once from a single IP address.
+    pub max_connections_per_ip: usize,
+}
+
+impl Default for WsLimits {
+    fn default() -> Self {
+        Self {
+            ping_interval: Duration::from_secs(30),
+            idle_timeout: Duration::from_secs(75),
+            send_queue: 64,
+            max_messages_per_second: 10,
+            max_connections_per_ip: 16,
+        }
+    }
+}
+
+fn env_var<T: std::str::FromStr>(name: &str) -> Result<Option<T>, Error> {
+    match std::env::var(name) {
+        Ok(value) => value
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|_| Error::Generic(format!("invalid `{name}` value `{value}`"))),
+        Err(_) => Ok(None),
+    }
+}
+
+impl WsLimits {
+    /// Reads `WS_PING_INTERVAL_SECS`, `WS_IDLE_TIMEOUT_SECS`,
+    /// `WS_SEND_QUEUE`, `WS_MAX_MESSAGES_PER_SECOND` and
+    /// `WS_MAX_CONNECTIONS_PER_IP`, keeping the defaults for the missing ones.
+    pub fn from_env() -> Result<Self, Error> {
+        let mut limits = Self::default();
+        if let Some(secs) = env_var("WS_PING_INTERVAL_SECS")? {
+            limits.ping_interval = Duration::from_secs(secs);
+        }
+        if let Some(secs) = env_var("WS_IDLE_TIMEOUT_SECS")? {
+            limits.idle_timeout = Duration::from_secs(secs);
+        }
+        if let Some(send_queue) = env_var("WS_SEND_QUEUE")? {
+            limits.send_queue = send_queue;
+        }
+        if let Some(rate) = env_var("WS_MAX_MESSAGES_PER_SECOND")? {
+            limits.max_messages_per_second = rate;
+        }
+        if let Some(connections) = env_var("WS_MAX_CONNECTIONS_PER_IP")? {
+            limits.max_connections_per_ip = connections;
+        }
+        if limits.send_queue == 0 || limits.ping_interval.is_zero() {
+            return Err(Error::Generic(
+                "`WS_SEND_QUEUE` and `WS_PING_INTERVAL_SECS` must not be 0".to_owned(),
+            ));
+        }
+        Ok(limits)
+    }
+}
+
+/// Open connections per IP address.
+#[derive(Debug, Clone, Default)]
+pub struct Connections(Arc<Mutex<HashMap<IpAddr, usize>>>);
+
+impl Connections {
+    /// Counts a new connection from `ip`, `None` when it already has `max`.
+    pub fn acquire(&self, ip: IpAddr, max: usize) -> Option<ConnectionGuard> {
+        let mut connections = self.0.lock().expect("connections lock poisoned");
+        if connections.get(&ip).is_some_and(|open| *open >= max) || max == 0 {
+            return None;
+        }
+        *connections.entry(ip).or_default() += 1;
+        METRICS.websocket_connections.inc();
+        Some(ConnectionGuard {
+            connections: self.clone(),
+            ip,
+        })
+    }
+
+    fn release(&self, ip: IpAddr) {
+        let mut connections = self.0.lock().expect("connections lock poisoned");
+        if let Some(open) = connections.get_mut(&ip) {
+            METRICS.websocket_connections.dec();
+            *open -= 1;
+            if *open == 0 {
+                connections.remove(&ip);
+            }
+        }
+    }
+}
+
+/// Counts as an open connection until dropped.
+#[derive(Debug)]
+pub struct ConnectionGuard {
+    connections: Connections,
+    ip: IpAddr,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.connections.release(self.ip);
+    }
+}
+
+/// Counts messages over one second windows.
+#[derive(Debug)]
+pub(super) struct RateLimit {
+    max_per_second: u32,
+    window_start: Instant,
+    count: u32,
+}
+
+impl RateLimit {
+    pub(super) fn new(max_per_second: u32, now: Instant) -> Self {
+        Self {
+            max_per_second,
+            window_start: now,
+            count: 0,
+        }
+    }
+
+    /// Counts a message, false when it goes over the limit.
+    pub(super) fn allow(&mut self, now: Instant) -> bool {
+        if now.duration_since(self.window_start) >= Duration::from_secs(1) {
+            self.window_start = now;
+            self.count = 0;
+        }
+        self.count += 1;
+        self.count <= self.max_per_second
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn connections_per_ip() {
+        let connections = Connections::default();
+        let ip = IpAddr::from([127, 0, 0, 1]);
+        let other_ip = IpAddr::from([10, 0, 0, 1]);
+
+        let first = connections.acquire(ip, 2).unwrap();
+        let _second = connections.acquire(ip, 2).unwrap();
+        assert!(connections.acquire(ip, 2).is_none());
+        assert!(connections.acquire(other_ip, 2).is_some());
+
+        drop(first);
+        assert!(connections.acquire(ip, 2).is_some());
+    }
+
+    #[test]
+    fn message_rate() {
+        let start = Instant::now();
+        let mut rate = RateLimit::new(2, start);
+        assert!(rate.allow(start));
+        assert!(rate.allow(start));
+        assert!(!rate.allow(start + Duration::from_millis(500)));
+        assert!(rate.allow(start + Duration::from_secs(1)));
+    }
+}
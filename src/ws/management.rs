@@ -1,26 +1,56 @@
 use std::collections::{HashMap, HashSet};
 
-use serde::Serialize;
-use tokio::sync::mpsc;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    sync::{mpsc, oneshot},
+    task::JoinHandle,
+};
 
-use crate::ws::minecraft::MinecraftJob;
+use crate::{
+    metrics::METRICS,
+    report::mc::favicon::Favicon,
+    ws::{
+        auth::AuthConfig,
+        bedrock::{BedrockJob, BedrockJobConfig},
+        minecraft::{MinecraftJob, MinecraftJobConfig},
+    },
+};
 
 #[derive(Debug)]
 pub enum Command {
     None,
     Register(mpsc::Sender<SubscriptionResponse>),
+    /// Unlocks the channels of a token on top of the public ones, in place of
+    /// the ones of an earlier token.
+    Authenticate(UniqueId, String),
+    /// Asks which channels the connection may subscribe to.
+    Channels(UniqueId),
     Subscribe(UniqueId, Channel),
+    /// Subscribes and sends the updates following the given event id instead
+    /// of a snapshot, when they are still known.
+    Resume(UniqueId, Channel, u64),
     Unsubscribe(UniqueId, Channel),
     Unregister(UniqueId),
+    /// Asks for the icon the Minecraft job last saw, needs no registration.
+    Favicon,
 }
 
 #[derive(Debug)]
 pub enum CommandResponse {
     None,
     Registered(UniqueId),
+    Authenticated(Vec<Channel>),
+    Channels(Vec<Channel>),
+    /// The token is unknown.
+    Unauthorized,
+    /// The connection may not subscribe to the channel.
+    Forbidden,
     Subscribed,
     Unsubscribed,
     Unregistered,
+    /// The id is not registered, or not anymore.
+    UnknownId,
+    Favicon(Option<Favicon>),
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -33,100 +63,242 @@ impl UniqueId {
     }
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum Channel {
     Minecraft,
+    Bedrock,
+}
+
+impl Channel {
+    pub const ALL: &'static [Channel] = &[Channel::Minecraft, Channel::Bedrock];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Channel::Minecraft => "minecraft",
+            Channel::Bedrock => "bedrock",
+        }
+    }
+}
+
+/// Keeps the `channel_subscriptions` gauge in step with the forwarders.
+fn count_subscription(channel: Channel, delta: i64) {
+    METRICS
+        .channel_subscriptions
+        .with_label_values(&[channel.as_str()])
+        .add(delta);
 }
 
 #[derive(Debug, Clone, Serialize)]
 pub struct SubscriptionResponse {
     pub channel: Channel,
+    /// Position of the message in the channel, see [`Command::Resume`].
+    #[serde(skip)]
+    pub event_id: u64,
     message: SubscriptionResponseMessage,
 }
 
+impl SubscriptionResponse {
+    pub(super) fn with_event_id(mut self, event_id: u64) -> Self {
+        self.event_id = event_id;
+        self
+    }
+}
+
 #[derive(Debug, Clone, Serialize)]
 #[serde(untagged)]
 enum SubscriptionResponseMessage {
     Minecraft(super::minecraft::UpdateMessage),
+    Bedrock(super::bedrock::UpdateMessage),
 }
 
 impl From<super::minecraft::UpdateMessage> for SubscriptionResponse {
     fn from(value: super::minecraft::UpdateMessage) -> Self {
         SubscriptionResponse {
             channel: Channel::Minecraft,
+            event_id: 0,
             message: SubscriptionResponseMessage::Minecraft(value),
         }
     }
 }
 
+impl From<super::bedrock::UpdateMessage> for SubscriptionResponse {
+    fn from(value: super::bedrock::UpdateMessage) -> Self {
+        SubscriptionResponse {
+            channel: Channel::Bedrock,
+            event_id: 0,
+            message: SubscriptionResponseMessage::Bedrock(value),
+        }
+    }
+}
+
+/// Configuration of the jobs feeding the channels.
+#[derive(Debug, Clone, Default)]
+pub struct ManagerConfig {
+    pub minecraft: MinecraftJobConfig,
+    pub bedrock: BedrockJobConfig,
+    pub auth: AuthConfig,
+}
+
 #[derive(Debug, Clone)]
 pub struct Manager {
-    command_sender: mpsc::Sender<(Command, mpsc::Sender<CommandResponse>)>,
+    command_sender: mpsc::Sender<(Command, oneshot::Sender<CommandResponse>)>,
 }
 
 impl Manager {
     pub fn new() -> Self {
+        Self::with_config(ManagerConfig::default())
+    }
+
+    pub fn with_minecraft_config(minecraft_config: MinecraftJobConfig) -> Self {
+        Self::with_config(ManagerConfig {
+            minecraft: minecraft_config,
+            ..Default::default()
+        })
+    }
+
+    pub fn with_config(config: ManagerConfig) -> Self {
         let (tx, rx) = mpsc::channel(16);
-        tokio::spawn(manager_service(rx));
+        tokio::spawn(manager_service(rx, config));
         Self { command_sender: tx }
     }
 
     pub async fn send_command(&self, command: Command) -> Option<CommandResponse> {
-        let (tx, mut rx) = mpsc::channel(1);
+        let (tx, rx) = oneshot::channel();
         if let Err(error) = self.command_sender.send((command, tx)).await {
             tracing::warn!("Error sending command to manager service: {error}");
         }
 
-        rx.recv().await
+        rx.await.ok()
     }
 }
 
+impl Default for Manager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Channels in the order of [`Channel::ALL`].
+fn ordered(channels: &HashSet<Channel>) -> Vec<Channel> {
+    Channel::ALL
+        .iter()
+        .filter(|channel| channels.contains(channel))
+        .copied()
+        .collect()
+}
+
 async fn manager_service(
-    mut command_receiver: mpsc::Receiver<(Command, mpsc::Sender<CommandResponse>)>,
+    mut command_receiver: mpsc::Receiver<(Command, oneshot::Sender<CommandResponse>)>,
+    config: ManagerConfig,
 ) {
-    let mut mc_job = MinecraftJob::new();
+    let mc_job = MinecraftJob::new(config.minecraft);
+    let bedrock_job = BedrockJob::new(config.bedrock);
 
     let mut current_unique_id = UniqueId(0);
-    let mut registered_sockets = HashSet::<UniqueId>::new();
     let mut registered_senders = HashMap::<UniqueId, mpsc::Sender<SubscriptionResponse>>::new();
+    let mut permissions = HashMap::<UniqueId, HashSet<Channel>>::new();
+    // every subscription is fed by its own task, see `Topic::forward`
+    let mut subscriptions = HashMap::<(UniqueId, Channel), JoinHandle<()>>::new();
 
     while let Some((command, tx)) = command_receiver.recv().await {
-        if let Err(send_error) = match command {
+        let response = match command {
+            Command::Authenticate(id, _)
+            | Command::Channels(id)
+            | Command::Subscribe(id, _)
+            | Command::Resume(id, _, _)
+            | Command::Unsubscribe(id, _)
+            | Command::Unregister(id)
+                if !registered_senders.contains_key(&id) =>
+            {
+                tracing::debug!("command for unknown id {id:?}");
+                CommandResponse::UnknownId
+            }
             Command::Register(sender) => {
                 let id = current_unique_id.next();
                 tracing::debug!("registering websocket id {id:?}");
-                registered_sockets.insert(id);
                 registered_senders.insert(id, sender);
-                tx.send(CommandResponse::Registered(id))
+                permissions.insert(id, config.auth.public_channels.clone());
+                CommandResponse::Registered(id)
             }
             Command::Unregister(id) => {
                 tracing::debug!("unregistering websocket with id {id:?}");
-                registered_sockets.remove(&id);
                 registered_senders.remove(&id);
-                tx.send(CommandResponse::Unregistered)
+                permissions.remove(&id);
+                subscriptions.retain(|(subscriber, channel), forward| {
+                    if *subscriber == id {
+                        forward.abort();
+                        count_subscription(*channel, -1);
+                    }
+                    *subscriber != id
+                });
+                CommandResponse::Unregistered
+            }
+            Command::Authenticate(id, token) => match config.auth.channels_for(Some(&token)) {
+                Some(channels) => {
+                    tracing::debug!("authenticated {id:?} for {channels:?}");
+                    // a weaker token than before takes channels away
+                    subscriptions.retain(|(subscriber, channel), forward| {
+                        let revoked = *subscriber == id && !channels.contains(channel);
+                        if revoked {
+                            tracing::debug!("unsubscribing {id:?} from revoked {channel:?}");
+                            forward.abort();
+                            count_subscription(*channel, -1);
+                        }
+                        !revoked
+                    });
+                    let response = CommandResponse::Authenticated(ordered(&channels));
+                    permissions.insert(id, channels);
+                    response
+                }
+                None => {
+                    tracing::debug!("{id:?} sent an unknown token");
+                    CommandResponse::Unauthorized
+                }
+            },
+            Command::Channels(id) => {
+                CommandResponse::Channels(permissions.get(&id).map(ordered).unwrap_or_default())
+            }
+            Command::Subscribe(id, channel) | Command::Resume(id, channel, _)
+                if !permissions
+                    .get(&id)
+                    .is_some_and(|channels| channels.contains(&channel)) =>
+            {
+                tracing::debug!("{id:?} may not subscribe to {channel:?}");
+                CommandResponse::Forbidden
             }
-            Command::Subscribe(id, channel) => {
+            Command::Subscribe(id, channel) | Command::Resume(id, channel, _) => {
+                let resume_after = match command {
+                    Command::Resume(_, _, after) => Some(after),
+                    _ => None,
+                };
+                let topic = match channel {
+                    Channel::Minecraft => mc_job.topic(),
+                    Channel::Bedrock => bedrock_job.topic(),
+                };
+                // registered, the guard at the top answers unknown ids
+                let sender = registered_senders[&id].clone();
                 tracing::debug!("subscribing {id:?} to {channel:?}");
-                match channel {
-                    Channel::Minecraft => {
-                        mc_job.subscribe(id, registered_senders[&id].clone()).await
-                    }
+                let forward = tokio::spawn(topic.forward(sender, resume_after));
+                match subscriptions.insert((id, channel), forward) {
+                    Some(previous) => previous.abort(),
+                    None => count_subscription(channel, 1),
                 }
-                tx.send(CommandResponse::Subscribed)
+                CommandResponse::Subscribed
             }
             Command::Unsubscribe(id, channel) => {
                 tracing::debug!("unsubscribing {id:?} from {channel:?}");
-                match channel {
-                    Channel::Minecraft => mc_job.unsubscribe(id).await,
+                if let Some(forward) = subscriptions.remove(&(id, channel)) {
+                    forward.abort();
+                    count_subscription(channel, -1);
                 }
-                tx.send(CommandResponse::Unsubscribed)
+                CommandResponse::Unsubscribed
             }
-            Command::None => tx.send(CommandResponse::None),
-        }
-        .await
-        {
-            tracing::warn!("error sending response back to websocket: {send_error}");
+            Command::Favicon => CommandResponse::Favicon(mc_job.favicon()),
+            Command::None => CommandResponse::None,
+        };
+        if tx.send(response).is_err() {
+            tracing::warn!("error sending response back to websocket: receiver dropped");
         }
     }
 
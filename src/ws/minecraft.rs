@@ -1,144 +1,178 @@
-use std::{collections::HashMap, time::Duration};
+use std::{net::SocketAddr, sync::Arc, time::Duration};
 
 use serde::Serialize;
-use tokio::sync::mpsc;
+use tokio::sync::watch;
 
 use crate::{
     error::Error,
-    report::mc::packet::{ping::PingResponse, status::StatusResponse},
+    metrics::METRICS,
+    report::mc::{
+        favicon::Favicon,
+        history::{History, Sample},
+        modpack::{self, ExpectedMod, ModpackDiff},
+        packet::{
+            ping::PingResponse,
+            status::{ForgeMod, StatusResponse},
+        },
+        sessions::Sessions,
+    },
 };
 
-use super::management::{SubscriptionResponse, UniqueId};
+use super::{
+    poll::Pollers,
+    supervisor::{supervise, RESTART_DELAY},
+    topic::{topic, Publisher, Topic},
+    writer::Writer,
+};
 
-pub struct MinecraftJob {
-    subscribe_sender: mpsc::Sender<(UniqueId, mpsc::Sender<SubscriptionResponse>)>,
-    unsubscribe_sender: mpsc::Sender<UniqueId>,
+#[derive(Debug, Clone)]
+pub struct MinecraftJobConfig {
+    /// Server to poll, read from the environment on every poll when `None`.
+    pub server_addr: Option<SocketAddr>,
+    /// How often to ping the server, `None` disables pinging.
+    pub ping_interval: Option<Duration>,
+    /// How often to ask for the full status, `None` disables status polling.
+    pub status_interval: Option<Duration>,
+    /// Where every poll result is recorded, `None` disables recording.
+    pub history: Option<History>,
+    /// Where player sessions from status samples are recorded, `None`
+    /// disables them.
+    pub sessions: Option<Sessions>,
 }
 
-impl MinecraftJob {
-    pub fn new() -> Self {
-        let (sub_tx, sub_rx) = mpsc::channel(32);
-        let (unsub_tx, unsub_rx) = mpsc::channel(32);
-
-        tokio::spawn(MinecraftJob::service(sub_rx, unsub_rx));
-
-        MinecraftJob {
-            subscribe_sender: sub_tx,
-            unsubscribe_sender: unsub_tx,
+impl Default for MinecraftJobConfig {
+    fn default() -> Self {
+        Self {
+            server_addr: None,
+            ping_interval: Some(Duration::from_secs(5)),
+            status_interval: Some(Duration::from_secs(60)),
+            history: None,
+            sessions: None,
         }
     }
+}
 
-    pub async fn subscribe(&mut self, id: UniqueId, sender: mpsc::Sender<SubscriptionResponse>) {
-        if let Err(err) = self.subscribe_sender.send((id, sender)).await {
-            tracing::warn!("failed to subscribe ws to MC job: {err}");
+impl MinecraftJobConfig {
+    fn server_addr(&self) -> Result<SocketAddr, Error> {
+        match self.server_addr {
+            Some(addr) => Ok(addr),
+            None => crate::report::mc::server_addr(),
         }
     }
+}
 
-    pub async fn unsubscribe(&mut self, id: UniqueId) {
-        if let Err(err) = self.unsubscribe_sender.send(id).await {
-            tracing::warn!("failed to unsubscribe ws to MC job: {err}");
-        }
+pub struct MinecraftJob {
+    topic: Topic,
+    favicon: watch::Receiver<Option<Favicon>>,
+}
+
+impl MinecraftJob {
+    pub fn new(config: MinecraftJobConfig) -> Self {
+        let (publisher, topic) = topic(MinecraftServerStatus::default().to_update_message());
+        let publisher = Arc::new(publisher);
+        let (favicon_sender, favicon) = watch::channel(None);
+        let favicon_sender = Arc::new(favicon_sender);
+        tokio::spawn(supervise("minecraft", RESTART_DELAY, move || {
+            MinecraftJob::service(config.clone(), publisher.clone(), favicon_sender.clone())
+        }));
+
+        MinecraftJob { topic, favicon }
+    }
+
+    pub(super) fn topic(&self) -> Topic {
+        self.topic.clone()
+    }
+
+    /// Icon of the last status response, `None` before the first one or when
+    /// the server has none.
+    pub(super) fn favicon(&self) -> Option<Favicon> {
+        self.favicon.borrow().clone()
     }
 
     async fn service(
-        mut sub_rx: mpsc::Receiver<(UniqueId, mpsc::Sender<SubscriptionResponse>)>,
-        mut unsub_rx: mpsc::Receiver<UniqueId>,
+        config: MinecraftJobConfig,
+        publisher: Arc<Publisher>,
+        favicon: Arc<watch::Sender<Option<Favicon>>>,
     ) {
         let mut current_server_status = MinecraftServerStatus::default();
-
-        let mut subscribers = HashMap::<UniqueId, mpsc::Sender<SubscriptionResponse>>::new();
-        let mut ids_to_unsub = vec![];
-
-        let (update_sender, mut update_receiver) = mpsc::channel(16);
-        let ping_update_sender = update_sender.clone();
-        let ping_task = async move {
-            loop {
-                let five_secs_later = tokio::time::Instant::now() + Duration::from_secs(5);
-                let ping_response = crate::report::mc::ping(1);
-                if let Err(err) = ping_update_sender
-                    .send(ping_response.map(ResponseMessage::Ping))
-                    .await
-                {
-                    tracing::error!("failed to send ping message `{err}`");
-                }
-                tokio::time::sleep_until(five_secs_later).await;
-            }
-        };
-        tokio::spawn(ping_task);
-
-        let status_update_sender = update_sender.clone();
-        let status_task = async move {
-            loop {
-                let one_minute_later = tokio::time::Instant::now() + Duration::from_secs(60);
-                let status_response = crate::report::mc::status();
-                if let Err(err) = status_update_sender
-                    .send(status_response.map(ResponseMessage::Status))
-                    .await
-                {
-                    tracing::error!("failed to send status message `{err}`");
-                }
-                tokio::time::sleep_until(one_minute_later).await;
+        let expected_mods = match modpack::expected_mods() {
+            Ok(expected_mods) => expected_mods,
+            Err(err) => {
+                tracing::error!("failed to load the modpack, not comparing mods: {err}");
+                None
             }
         };
-        tokio::spawn(status_task);
-
-        loop {
-            for id in ids_to_unsub.iter() {
-                subscribers.remove(id);
-            }
-            ids_to_unsub.clear();
-
-            let sub_task = sub_rx.recv();
-            let unsub_task = unsub_rx.recv();
 
-            let update_task = update_receiver.recv();
-
-            tokio::select! {
-                new_sub = sub_task => {
-                    if let Some((id, sender)) = new_sub {
-                        tracing::debug!("subbing {id:?} to minecraft");
-                        if let Err(error) = sender
-                            .send(current_server_status.to_update_message().into())
-                            .await
-                        {
-                            tracing::warn!("failed to send mc message: {error}");
-                        }
-
-                        subscribers.insert(id,sender);
-                    }
+        let writer = Writer::spawn("minecraft");
+        let mut pollers = Pollers::new("minecraft");
+        if let Some(ping_interval) = config.ping_interval {
+            let config = config.clone();
+            pollers.spawn("ping", ping_interval, move || {
+                crate::report::mc::ping_at(config.server_addr()?, 1).map(ResponseMessage::Ping)
+            });
+        }
+        if let Some(status_interval) = config.status_interval {
+            let config = config.clone();
+            pollers.spawn("status", status_interval, move || {
+                crate::report::mc::status_at(config.server_addr()?)
+                    .map(|status| ResponseMessage::Status(Box::new(status)))
+            });
+        }
 
-                },
-                unsub = unsub_task => {
-                    if let Some(id) = unsub {
-                        tracing::debug!("unsubbing {id:?} from minecraft");
-                        subscribers.remove(&id);
-                    }
-                }
-                message = update_task => {
-                    let update_message = match message {
-                        None => {tracing::error!("mc update channel closed.."); return},
-                        Some(message) => current_server_status.apply_response(message)
-                    };
-                    if let Some(update_message) = update_message {
-                        for (id, sender) in subscribers.iter_mut() {
-                            if let Err(error) = sender
-                                .send(update_message.clone().into())
-                                .await
-                            {
-                                tracing::warn!("failed to send mc message: {error}, unsubbing");
-                                ids_to_unsub.push(*id);
-                            }
-                        }
+        while let Some(message) = pollers.next().await {
+            if let Some(history) = &config.history {
+                let history = history.clone();
+                let sample = history_sample(&message);
+                writer.write(move || history.record(sample));
+            }
+            if let Some(sessions) = &config.sessions {
+                let sessions = sessions.clone();
+                match &message {
+                    Ok(ResponseMessage::Status(status)) => {
+                        let players = status.players.clone();
+                        writer.write(move || sessions.observe(&players));
                     }
+                    Ok(ResponseMessage::Ping(_)) => {}
+                    Err(_) => writer.write(move || sessions.poll_failed()),
                 }
             }
+            let update_messages =
+                current_server_status.apply_response(message, expected_mods.as_deref());
+            current_server_status.record_gauges();
+            favicon.send_if_modified(|favicon| {
+                let hash = |favicon: &Option<Favicon>| favicon.as_ref().map(|f| f.hash.clone());
+                let changed = hash(favicon) != hash(&current_server_status.favicon);
+                if changed {
+                    favicon.clone_from(&current_server_status.favicon);
+                }
+                changed
+            });
+            publisher.publish(update_messages, || {
+                current_server_status.to_update_message()
+            });
         }
     }
 }
 
 enum ResponseMessage {
     Ping(PingResponse),
-    Status(StatusResponse),
+    Status(Box<StatusResponse>),
+}
+
+fn history_sample(response: &Result<ResponseMessage, Error>) -> Sample {
+    match response {
+        Err(_) => Sample::default(),
+        Ok(ResponseMessage::Ping(ping)) => Sample {
+            online: true,
+            players_online: None,
+            latency_ms: ping.latency.ping_ms,
+        },
+        Ok(ResponseMessage::Status(status)) => Sample {
+            online: true,
+            players_online: Some(status.players.online),
+            latency_ms: None,
+        },
+    }
 }
 
 #[derive(Debug, Serialize, Clone)]
@@ -149,14 +183,77 @@ pub struct MinecraftServerStatus {
     description: String,
     players: Players,
     version: String,
-    favicon: String,
-    mods: HashMap<String, String>,
+    protocol: i32,
+    expected_protocol: Option<i32>,
+    version_matches: Option<bool>,
+    favicon: Option<Favicon>,
+    mods: Vec<ForgeMod>,
+    /// Set when the server left mods out of `mods`.
+    mods_truncated: bool,
+    /// Differences to the configured modpack, `None` without a modpack.
+    modpack_diff: Option<ModpackDiff>,
+    latency_ms: Option<f64>,
+    /// Set once a status response filled in `players`, before that there is
+    /// no sample to report joins and leaves against.
+    #[serde(skip)]
+    sampled: bool,
+}
+
+/// Latency changes smaller than either of these are not worth an update.
+const LATENCY_CHANGE_MS: f64 = 20.0;
+const LATENCY_CHANGE_RATIO: f64 = 0.25;
+
+pub(super) fn is_significant_latency_change(old: Option<f64>, new: Option<f64>) -> bool {
+    match (old, new) {
+        (Some(old), Some(new)) => {
+            let change = (new - old).abs();
+            change >= LATENCY_CHANGE_MS && change >= old * LATENCY_CHANGE_RATIO
+        }
+        (None, None) => false,
+        _ => true,
+    }
 }
 
 #[derive(Debug, Serialize, Clone)]
 pub struct Players {
     online: i32,
     max: i32,
+    sample: Vec<Player>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct Player {
+    name: String,
+    id: String,
+}
+
+impl Players {
+    /// Servers only send a (possibly random) subset of the online players, so
+    /// joins and leaves are only reported when both samples list everyone.
+    fn is_sample_complete(&self) -> bool {
+        self.sample.len() == self.online as usize
+    }
+
+    fn sample_changes(&self, new: &Players) -> Vec<UpdateMessage> {
+        if !self.is_sample_complete() || !new.is_sample_complete() {
+            return vec![];
+        }
+
+        let left = self
+            .sample
+            .iter()
+            .filter(|player| !new.sample.contains(player))
+            .cloned()
+            .map(UpdateMessage::PlayerLeft);
+        let joined = new
+            .sample
+            .iter()
+            .filter(|player| !self.sample.contains(player))
+            .cloned()
+            .map(UpdateMessage::PlayerJoined);
+
+        left.chain(joined).collect()
+    }
 }
 
 impl Default for MinecraftServerStatus {
@@ -165,10 +262,21 @@ impl Default for MinecraftServerStatus {
             online: false,
             reason_for_offline: Some("server status uninitialized".to_owned()),
             description: String::new(),
-            players: Players { online: 0, max: 20 },
+            players: Players {
+                online: 0,
+                max: 20,
+                sample: vec![],
+            },
             version: "0.0.0".to_owned(),
-            favicon: String::new(),
-            mods: HashMap::new(),
+            protocol: 0,
+            expected_protocol: crate::report::mc::expected_protocol_version(),
+            version_matches: None,
+            favicon: None,
+            mods: vec![],
+            mods_truncated: false,
+            modpack_diff: None,
+            latency_ms: None,
+            sampled: false,
         }
     }
 }
@@ -191,40 +299,115 @@ impl MinecraftServerStatus {
         })
     }
 
+    fn to_version_message(&self) -> UpdateMessage {
+        UpdateMessage::Version(VersionMessage {
+            version: self.version.clone(),
+            protocol: self.protocol,
+            expected_protocol: self.expected_protocol,
+            version_matches: self.version_matches,
+        })
+    }
+
+    fn to_latency_message(&self) -> UpdateMessage {
+        UpdateMessage::Latency(LatencyMessage {
+            latency_ms: self.latency_ms,
+        })
+    }
+
+    fn to_mods_message(&self) -> UpdateMessage {
+        UpdateMessage::Mods(ModsMessage {
+            mods: self.mods.clone(),
+            mods_truncated: self.mods_truncated,
+            modpack_diff: self.modpack_diff.clone(),
+        })
+    }
+
+    fn to_favicon_message(&self) -> UpdateMessage {
+        UpdateMessage::Favicon(FaviconMessage {
+            favicon: self.favicon.clone(),
+        })
+    }
+
+    fn record_gauges(&self) {
+        METRICS
+            .server_online
+            .with_label_values(&["minecraft"])
+            .set(self.online.into());
+        METRICS
+            .players_online
+            .with_label_values(&["minecraft"])
+            .set(self.players.online.into());
+        METRICS
+            .players_max
+            .with_label_values(&["minecraft"])
+            .set(self.players.max.into());
+    }
+
     fn apply_response(
         &mut self,
         response: Result<ResponseMessage, Error>,
-    ) -> Option<UpdateMessage> {
+        expected_mods: Option<&[ExpectedMod]>,
+    ) -> Vec<UpdateMessage> {
         match response {
             Err(err) => {
                 let changed = self.online;
                 self.online = false;
                 self.reason_for_offline = Some(err.to_string());
+                self.latency_ms = None;
 
                 if changed {
-                    Some(self.to_status_message())
+                    vec![self.to_status_message()]
                 } else {
-                    None
+                    vec![]
                 }
             }
             Ok(response) => match response {
-                ResponseMessage::Ping(_ping) => {
+                ResponseMessage::Ping(ping) => {
+                    let mut update_messages = vec![];
+
                     let changed = !self.online;
                     self.online = true;
                     self.reason_for_offline = None;
                     if changed {
-                        Some(self.to_status_message())
-                    } else {
-                        None
+                        update_messages.push(self.to_status_message());
                     }
+
+                    // compared against the last reported latency, so slow drift
+                    // is reported once it adds up
+                    if is_significant_latency_change(self.latency_ms, ping.latency.ping_ms) {
+                        self.latency_ms = ping.latency.ping_ms;
+                        update_messages.push(self.to_latency_message());
+                    }
+
+                    update_messages
+                }
+                ResponseMessage::Status(status) => {
+                    self.apply_status_response(*status, expected_mods)
                 }
-                ResponseMessage::Status(status) => self.apply_status_response(status),
             },
         }
     }
 
-    fn apply_status_response(&mut self, status: StatusResponse) -> Option<UpdateMessage> {
-        let online_players_changed = self.players.online != status.players.online;
+    fn apply_status_response(
+        &mut self,
+        status: StatusResponse,
+        expected_mods: Option<&[ExpectedMod]>,
+    ) -> Vec<UpdateMessage> {
+        let was_online = self.online;
+        let was_sampled = self.sampled;
+        let previous_players = self.players.clone();
+        let previous_version = (self.version.clone(), self.protocol);
+        let previous_favicon_hash = self.favicon.as_ref().map(|favicon| favicon.hash.clone());
+        let previous_mods = std::mem::take(&mut self.mods);
+        let previous_modpack_diff = self.modpack_diff.take();
+
+        let expected_protocol = crate::report::mc::expected_protocol_version();
+        let (mods, mods_truncated) = status
+            .forge_data
+            .map(|forge_data| (forge_data.d.mods, forge_data.d.truncated))
+            .unwrap_or_default();
+        let modpack_diff =
+            expected_mods.map(|expected| modpack::diff(expected, &mods, mods_truncated));
 
         *self = MinecraftServerStatus {
             online: true,
@@ -232,27 +415,39 @@ impl MinecraftServerStatus {
             description: status.description.text,
             players: status.players.into(),
             version: status.version.name,
+            protocol: status.version.protocol,
+            expected_protocol,
+            version_matches: expected_protocol.map(|expected| expected == status.version.protocol),
             favicon: status.favicon,
-            mods: status
-                .forge_data
-                .d
-                .mods
-                .into_iter()
-                .map(|(mod_name, mod_version)| {
-                    if mod_version.is_empty() {
-                        (mod_name, "not specified".into())
-                    } else {
-                        (mod_name, mod_version)
-                    }
-                })
-                .collect(),
+            mods,
+            mods_truncated,
+            modpack_diff,
+            latency_ms: self.latency_ms,
+            sampled: true,
         };
 
-        if online_players_changed {
-            Some(self.to_online_players())
-        } else {
-            None
+        let mut update_messages = vec![];
+        if !was_online {
+            update_messages.push(self.to_status_message());
         }
+        // the first sample is the baseline, its players did not just join
+        if was_sampled {
+            update_messages.extend(previous_players.sample_changes(&self.players));
+        }
+        if previous_players.online != self.players.online {
+            update_messages.push(self.to_online_players());
+        }
+        if previous_version != (self.version.clone(), self.protocol) {
+            update_messages.push(self.to_version_message());
+        }
+        if previous_favicon_hash.as_ref() != self.favicon.as_ref().map(|favicon| &favicon.hash) {
+            update_messages.push(self.to_favicon_message());
+        }
+        if previous_mods != self.mods || previous_modpack_diff != self.modpack_diff {
+            update_messages.push(self.to_mods_message());
+        }
+
+        update_messages
     }
 }
 
@@ -262,6 +457,12 @@ pub enum UpdateMessage {
     Initial(MinecraftServerStatus),
     Status(StatusMessage),
     OnlinePlayers(OnlinePlayersMessage),
+    PlayerJoined(Player),
+    PlayerLeft(Player),
+    Version(VersionMessage),
+    Favicon(FaviconMessage),
+    Latency(LatencyMessage),
+    Mods(ModsMessage),
 }
 
 impl From<crate::report::mc::packet::status::Players> for Players {
@@ -269,6 +470,14 @@ impl From<crate::report::mc::packet::status::Players> for Players {
         Self {
             online: value.online,
             max: value.max,
+            sample: value
+                .sample
+                .into_iter()
+                .map(|player| Player {
+                    name: player.name,
+                    id: player.id,
+                })
+                .collect(),
         }
     }
 }
@@ -276,12 +485,40 @@ impl From<crate::report::mc::packet::status::Players> for Players {
 #[derive(Debug, Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct StatusMessage {
-    online: bool,
-    reason_for_offline: Option<String>,
+    pub(super) online: bool,
+    pub(super) reason_for_offline: Option<String>,
 }
 
 #[derive(Debug, Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct OnlinePlayersMessage {
-    players_online: i32,
+    pub(super) players_online: i32,
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct VersionMessage {
+    version: String,
+    protocol: i32,
+    expected_protocol: Option<i32>,
+    version_matches: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct FaviconMessage {
+    favicon: Option<Favicon>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LatencyMessage {
+    pub(super) latency_ms: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ModsMessage {
+    mods: Vec<ForgeMod>,
+    mods_truncated: bool,
+    modpack_diff: Option<ModpackDiff>,
 }
@@ -1,4 +1,13 @@
+pub mod auth;
+pub mod bedrock;
 pub mod handler;
+pub mod limits;
 pub mod management;
 
-mod minecraft;
+pub mod minecraft;
+mod poll;
+mod replay;
+pub mod sse;
+mod supervisor;
+mod topic;
+mod writer;
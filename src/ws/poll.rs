@@ -0,0 +1,66 @@
+//! The polling loop shared by the server jobs: every poll runs the blocking
+//! report call on its own interval and hands the result to the job's service
+//! through one channel.
+
+use std::time::{Duration, Instant};
+
+use tokio::{sync::mpsc, task::JoinSet};
+
+use crate::{error::Error, metrics::METRICS};
+
+pub(super) struct Pollers<T> {
+    /// Job name, the `server` label of the poll metrics.
+    job: &'static str,
+    sender: mpsc::Sender<Result<T, Error>>,
+    receiver: mpsc::Receiver<Result<T, Error>>,
+    // dropping the set stops the polls along with the service
+    tasks: JoinSet<()>,
+}
+
+impl<T: Send + 'static> Pollers<T> {
+    pub(super) fn new(job: &'static str) -> Self {
+        let (sender, receiver) = mpsc::channel(16);
+        Self {
+            job,
+            sender,
+            receiver,
+            tasks: JoinSet::new(),
+        }
+    }
+
+    /// Runs `poll` on the blocking pool every `interval`, recording it as
+    /// `kind` in the metrics.
+    pub(super) fn spawn<F>(&mut self, kind: &'static str, interval: Duration, poll: F)
+    where
+        F: Fn() -> Result<T, Error> + Clone + Send + 'static,
+    {
+        let job = self.job;
+        let sender = self.sender.clone();
+        self.tasks.spawn(async move {
+            loop {
+                let next_poll = tokio::time::Instant::now() + interval;
+                let start = Instant::now();
+                let response = tokio::task::spawn_blocking(poll.clone())
+                    .await
+                    .expect("poll task should not panic");
+                METRICS.record_poll(job, kind, start, &response);
+                if let Err(err) = sender.send(response).await {
+                    tracing::error!("failed to send {job} {kind} message `{err}`");
+                }
+                tokio::time::sleep_until(next_poll).await;
+            }
+        });
+    }
+
+    /// The next poll result, `None` once a poll stopped.
+    pub(super) async fn next(&mut self) -> Option<Result<T, Error>> {
+        tokio::select! {
+            Some(response) = self.receiver.recv() => Some(response),
+            // the polls never end on their own, so this one panicked
+            Some(poll) = self.tasks.join_next() => {
+                tracing::error!("{} poll task stopped: {poll:?}", self.job);
+                None
+            }
+        }
+    }
+}
@@ -0,0 +1,99 @@
+//! Numbers the updates of a channel and keeps the latest ones around, so a
+//! client that reconnects can be sent what it missed instead of a snapshot.
+
+use std::{
+    collections::VecDeque,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// How many updates a channel keeps for clients resuming their stream.
+const REPLAY_CAPACITY: usize = 128;
+
+#[derive(Debug)]
+pub(super) struct Replay<T> {
+    /// Id of the latest update, which is also the id of the current snapshot.
+    last_id: u64,
+    updates: VecDeque<(u64, T)>,
+}
+
+impl<T: Clone> Replay<T> {
+    /// Ids start at the current time in milliseconds, so the ids handed out
+    /// before a restart never point into the updates of the new process.
+    pub(super) fn new() -> Self {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_millis() as u64);
+        Self::starting_at(now)
+    }
+
+    fn starting_at(last_id: u64) -> Self {
+        Self {
+            last_id,
+            updates: VecDeque::with_capacity(REPLAY_CAPACITY),
+        }
+    }
+
+    pub(super) fn last_id(&self) -> u64 {
+        self.last_id
+    }
+
+    /// Stores an update and returns its id.
+    pub(super) fn push(&mut self, update: T) -> u64 {
+        self.last_id += 1;
+        if self.updates.len() == REPLAY_CAPACITY {
+            self.updates.pop_front();
+        }
+        self.updates.push_back((self.last_id, update));
+        self.last_id
+    }
+
+    /// Updates following `after`, `None` when some of them were already
+    /// dropped or `after` was never handed out.
+    pub(super) fn since(&self, after: u64) -> Option<Vec<(u64, T)>> {
+        if after > self.last_id {
+            return None;
+        }
+        let oldest_kept = self.updates.front().map_or(self.last_id + 1, |(id, _)| *id);
+        if after + 1 < oldest_kept {
+            return None;
+        }
+        Some(
+            self.updates
+                .iter()
+                .filter(|(id, _)| *id > after)
+                .cloned()
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resume_after_known_id() {
+        let mut replay = Replay::starting_at(10);
+        assert_eq!(replay.since(10), Some(vec![]));
+        assert_eq!(replay.push("a"), 11);
+        assert_eq!(replay.push("b"), 12);
+
+        assert_eq!(replay.since(10), Some(vec![(11, "a"), (12, "b")]));
+        assert_eq!(replay.since(11), Some(vec![(12, "b")]));
+        assert_eq!(replay.since(12), Some(vec![]));
+        assert_eq!(replay.since(13), None);
+        assert_eq!(replay.since(9), None);
+    }
+
+    #[test]
+    fn forgets_old_updates() {
+        let mut replay = Replay::starting_at(0);
+        for update in 0..REPLAY_CAPACITY + 1 {
+            replay.push(update);
+        }
+
+        assert_eq!(replay.since(0), None);
+        assert_eq!(replay.since(1).unwrap().len(), REPLAY_CAPACITY);
+        assert_eq!(replay.last_id(), REPLAY_CAPACITY as u64 + 1);
+    }
+}
@@ -0,0 +1,130 @@
+//! Server-Sent Events, for clients that cannot open a WebSocket. A stream
+//! follows a single channel and carries the same messages as a subscribed
+//! socket, each with its event id so `Last-Event-ID` can resume it.
+
+use axum::{
+    extract::{Query, State},
+    headers::{authorization::Bearer, Authorization},
+    http::{HeaderMap, StatusCode},
+    response::sse::{Event, KeepAlive, Sse},
+    Json, TypedHeader,
+};
+use futures_util::{stream, Stream};
+use serde::Deserialize;
+use tokio::sync::mpsc;
+
+use crate::{error::Error, response::Response};
+
+use super::management::{Channel, Command, CommandResponse, Manager, UniqueId};
+
+const LAST_EVENT_ID: &str = "last-event-id";
+
+#[derive(Debug, Deserialize)]
+pub struct EventsQuery {
+    channel: Channel,
+    token: Option<String>,
+}
+
+type EventsError = (StatusCode, Json<Response<()>>);
+
+fn events_error(status: StatusCode, error: &str) -> EventsError {
+    (
+        status,
+        Json(Response::err(Error::Generic(error.to_owned()))),
+    )
+}
+
+/// Registers with the manager like a socket does, and unsubscribes once the
+/// stream is dropped.
+struct Registration {
+    manager: Manager,
+    id: UniqueId,
+    channel: Channel,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        let manager = self.manager.clone();
+        let (id, channel) = (self.id, self.channel);
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            runtime.spawn(async move {
+                manager
+                    .send_command(Command::Unsubscribe(id, channel))
+                    .await;
+                manager.send_command(Command::Unregister(id)).await;
+            });
+        }
+    }
+}
+
+pub async fn sse_handler(
+    State(manager): State<Manager>,
+    Query(query): Query<EventsQuery>,
+    authorization: Option<TypedHeader<Authorization<Bearer>>>,
+    headers: HeaderMap,
+) -> Result<Sse<impl Stream<Item = Result<Event, serde_json::Error>>>, EventsError> {
+    let (sender, receiver) = mpsc::channel(16);
+    let Some(CommandResponse::Registered(id)) =
+        manager.send_command(Command::Register(sender)).await
+    else {
+        return Err(events_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "the stream could not be registered",
+        ));
+    };
+    tracing::debug!("event stream registered as {id:?}");
+    let registration = Registration {
+        manager: manager.clone(),
+        id,
+        channel: query.channel,
+    };
+
+    let token = query
+        .token
+        .or_else(|| authorization.map(|TypedHeader(auth)| auth.token().to_owned()));
+    if let Some(token) = token {
+        if let Some(CommandResponse::Unauthorized) | None =
+            manager.send_command(Command::Authenticate(id, token)).await
+        {
+            return Err(events_error(StatusCode::UNAUTHORIZED, "invalid token"));
+        }
+    }
+
+    // an id we cannot read is treated like a fresh connection
+    let last_event_id = headers
+        .get(LAST_EVENT_ID)
+        .and_then(|id| id.to_str().ok())
+        .and_then(|id| id.trim().parse().ok());
+    let command = match last_event_id {
+        Some(after) => Command::Resume(id, query.channel, after),
+        None => Command::Subscribe(id, query.channel),
+    };
+    match manager.send_command(command).await {
+        Some(CommandResponse::Subscribed) => {}
+        Some(CommandResponse::Forbidden) => {
+            return Err(events_error(
+                StatusCode::FORBIDDEN,
+                "not allowed to subscribe to this channel",
+            ))
+        }
+        _ => {
+            return Err(events_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "the command could not be handled",
+            ))
+        }
+    }
+    tracing::debug!("event stream {id:?} following {:?}", query.channel);
+
+    let events = stream::unfold(
+        (receiver, registration),
+        |(mut receiver, registration)| async move {
+            let response = receiver.recv().await?;
+            let event = Event::default()
+                .id(response.event_id.to_string())
+                .json_data(&response);
+            Some((event, (receiver, registration)))
+        },
+    );
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
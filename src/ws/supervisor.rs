@@ -0,0 +1,66 @@
+//! Keeps the jobs feeding the channels alive.
+
+use std::{future::Future, time::Duration};
+
+/// How long a crashed job waits before starting again.
+pub(super) const RESTART_DELAY: Duration = Duration::from_secs(5);
+
+/// Runs a job's service, starting it again whenever it panics or stops, until
+/// the runtime shuts down.
+pub(super) async fn supervise<F, Fut>(name: &'static str, restart_delay: Duration, mut service: F)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    loop {
+        match tokio::spawn(service()).await {
+            Ok(()) => tracing::error!("{name} job stopped, restarting it"),
+            Err(error) if error.is_panic() => {
+                tracing::error!("{name} job panicked, restarting it")
+            }
+            Err(_) => return,
+        }
+        tokio::time::sleep(restart_delay).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn restarts_crashed_service() {
+        let starts = Arc::new(AtomicUsize::new(0));
+        let (done_tx, done_rx) = tokio::sync::oneshot::channel();
+        let mut done_tx = Some(done_tx);
+
+        let service_starts = starts.clone();
+        tokio::spawn(supervise("test", Duration::ZERO, move || {
+            let start = service_starts.fetch_add(1, Ordering::SeqCst);
+            let done_tx = if start == 2 { done_tx.take() } else { None };
+            async move {
+                match start {
+                    0 => panic!("first start crashes"),
+                    1 => {}
+                    _ => {
+                        if let Some(done_tx) = done_tx {
+                            let _ = done_tx.send(());
+                        }
+                        std::future::pending::<()>().await
+                    }
+                }
+            }
+        }));
+
+        tokio::time::timeout(Duration::from_secs(5), done_rx)
+            .await
+            .expect("service should be restarted")
+            .unwrap();
+        assert_eq!(starts.load(Ordering::SeqCst), 3);
+    }
+}
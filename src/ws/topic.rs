@@ -0,0 +1,196 @@
+//! Fans the updates of a channel out to its subscribers. A job publishes once
+//! to a broadcast channel and every subscriber is fed by its own task, so a
+//! slow client only holds up itself. Clients falling too far behind skip
+//! ahead to the current snapshot.
+
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::{
+    broadcast::{self, error::RecvError},
+    mpsc, watch,
+};
+
+use super::{management::SubscriptionResponse, replay::Replay};
+
+/// Updates a subscriber may fall behind by before it is sent a snapshot.
+const TOPIC_CAPACITY: usize = 64;
+
+/// Publishing side of a topic, owned by its job.
+pub(super) struct Publisher {
+    updates: broadcast::Sender<SubscriptionResponse>,
+    snapshot: watch::Sender<SubscriptionResponse>,
+    replay: Arc<Mutex<Replay<SubscriptionResponse>>>,
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct Topic {
+    updates: broadcast::Sender<SubscriptionResponse>,
+    snapshot: watch::Receiver<SubscriptionResponse>,
+    replay: Arc<Mutex<Replay<SubscriptionResponse>>>,
+}
+
+pub(super) fn topic(snapshot: impl Into<SubscriptionResponse>) -> (Publisher, Topic) {
+    let replay = Replay::new();
+    let (snapshot_tx, snapshot_rx) =
+        watch::channel(snapshot.into().with_event_id(replay.last_id()));
+    let (updates, _) = broadcast::channel(TOPIC_CAPACITY);
+    let replay = Arc::new(Mutex::new(replay));
+
+    let publisher = Publisher {
+        updates: updates.clone(),
+        snapshot: snapshot_tx,
+        replay: replay.clone(),
+    };
+    let topic = Topic {
+        updates,
+        snapshot: snapshot_rx,
+        replay,
+    };
+    (publisher, topic)
+}
+
+impl Publisher {
+    /// Publishes updates along with the snapshot they lead to.
+    pub(super) fn publish<T>(&self, updates: Vec<T>, snapshot: impl FnOnce() -> T)
+    where
+        T: Into<SubscriptionResponse>,
+    {
+        // the replay and the snapshot are updated before broadcasting, so a
+        // subscriber joining in between gets every update exactly once
+        let updates: Vec<_> = {
+            let mut replay = self.replay.lock().expect("replay lock poisoned");
+            let updates = updates
+                .into_iter()
+                .map(|update| {
+                    let update = update.into().with_event_id(replay.last_id() + 1);
+                    replay.push(update.clone());
+                    update
+                })
+                .collect();
+            self.snapshot
+                .send_replace(snapshot().into().with_event_id(replay.last_id()));
+            updates
+        };
+
+        for update in updates {
+            // no receivers just means nobody is subscribed
+            let _ = self.updates.send(update);
+        }
+    }
+}
+
+impl Topic {
+    /// Feeds a subscriber until it goes away or the topic closes. Sends the
+    /// updates following `resume_after` first when they are still known, the
+    /// current snapshot otherwise.
+    pub(super) async fn forward(
+        self,
+        sender: mpsc::Sender<SubscriptionResponse>,
+        resume_after: Option<u64>,
+    ) {
+        let mut updates = self.updates.subscribe();
+
+        let missed = resume_after.and_then(|after| {
+            let replay = self.replay.lock().expect("replay lock poisoned");
+            replay.since(after)
+        });
+        let catch_up = match missed {
+            Some(missed) => missed.into_iter().map(|(_, update)| update).collect(),
+            None => vec![self.snapshot.borrow().clone()],
+        };
+        let mut last_sent = resume_after.unwrap_or_default();
+        for response in catch_up {
+            last_sent = response.event_id;
+            if sender.send(response).await.is_err() {
+                return;
+            }
+        }
+
+        loop {
+            let response = match updates.recv().await {
+                Ok(response) if response.event_id <= last_sent => continue,
+                Ok(response) => response,
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::warn!("subscriber lagged {skipped} updates behind, sending snapshot");
+                    updates = updates.resubscribe();
+                    self.snapshot.borrow().clone()
+                }
+                Err(RecvError::Closed) => return,
+            };
+            last_sent = response.event_id;
+            if sender.send(response).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+    use crate::ws::minecraft::{OnlinePlayersMessage, UpdateMessage};
+
+    fn players(players_online: i32) -> UpdateMessage {
+        UpdateMessage::OnlinePlayers(OnlinePlayersMessage { players_online })
+    }
+
+    fn players_online(response: &SubscriptionResponse) -> Value {
+        serde_json::to_value(response).unwrap()["message"]["playersOnline"].clone()
+    }
+
+    #[tokio::test]
+    async fn snapshot_then_updates() {
+        let (publisher, topic) = topic(players(0));
+        publisher.publish(vec![players(1), players(2)], || players(2));
+
+        let (tx, mut rx) = mpsc::channel(16);
+        tokio::spawn(topic.forward(tx, None));
+        let snapshot = rx.recv().await.unwrap();
+        assert_eq!(players_online(&snapshot), 2);
+
+        publisher.publish(vec![players(3)], || players(3));
+        let update = rx.recv().await.unwrap();
+        assert_eq!(players_online(&update), 3);
+        assert_eq!(update.event_id, snapshot.event_id + 1);
+    }
+
+    #[tokio::test]
+    async fn resume_replays_missed_updates() {
+        let (publisher, topic) = topic(players(0));
+        let (tx, mut rx) = mpsc::channel(16);
+        tokio::spawn(topic.clone().forward(tx, None));
+        let snapshot = rx.recv().await.unwrap();
+
+        publisher.publish(vec![players(1), players(2)], || players(2));
+        let (tx, mut rx) = mpsc::channel(16);
+        tokio::spawn(topic.forward(tx, Some(snapshot.event_id + 1)));
+        let update = rx.recv().await.unwrap();
+        assert_eq!(players_online(&update), 2);
+        assert_eq!(update.event_id, snapshot.event_id + 2);
+    }
+
+    #[tokio::test]
+    async fn slow_subscriber_skips_to_snapshot() {
+        let (publisher, topic) = topic(players(0));
+        let (tx, mut rx) = mpsc::channel(1);
+        tokio::spawn(topic.forward(tx, None));
+        rx.recv().await.unwrap();
+
+        let published = TOPIC_CAPACITY as i32 * 4;
+        for count in 1..=published {
+            publisher.publish(vec![players(count)], || players(count));
+        }
+
+        let mut received = 0;
+        loop {
+            let response = rx.recv().await.unwrap();
+            received += 1;
+            if players_online(&response) == published {
+                break;
+            }
+        }
+        assert!(received < published);
+    }
+}
@@ -0,0 +1,61 @@
+//! Runs a job's file writes on a thread of their own, so a slow disk or a
+//! day's compaction doesn't hold up the updates.
+
+use tokio::sync::mpsc;
+
+type Write = Box<dyn FnOnce() + Send>;
+
+pub(super) struct Writer {
+    name: &'static str,
+    sender: mpsc::UnboundedSender<Write>,
+}
+
+impl Writer {
+    /// Starts the thread, it finishes the queued writes and stops once the
+    /// writer is dropped.
+    pub(super) fn spawn(name: &'static str) -> Self {
+        let (sender, mut receiver) = mpsc::unbounded_channel::<Write>();
+        let thread = std::thread::Builder::new()
+            .name(format!("{name}-writer"))
+            .spawn(move || {
+                while let Some(write) = receiver.blocking_recv() {
+                    write();
+                }
+            });
+        if let Err(err) = thread {
+            tracing::error!("failed to start the {name} writer: {err}");
+        }
+
+        Self { name, sender }
+    }
+
+    /// Queues a write, writes run one after another in the order they were
+    /// queued.
+    pub(super) fn write(&self, write: impl FnOnce() + Send + 'static) {
+        if self.sender.send(Box::new(write)).is_err() {
+            tracing::error!("{} writer stopped, dropping a write", self.name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    #[test]
+    fn writes_in_order() {
+        let written = Arc::new(Mutex::new(vec![]));
+        let writer = Writer::spawn("test");
+        for value in 0..10 {
+            let written = written.clone();
+            writer.write(move || written.lock().unwrap().push(value));
+        }
+
+        let (done_tx, done_rx) = std::sync::mpsc::channel();
+        writer.write(move || done_tx.send(()).unwrap());
+        done_rx.recv().unwrap();
+        assert_eq!(*written.lock().unwrap(), (0..10).collect::<Vec<_>>());
+    }
+}
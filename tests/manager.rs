@@ -0,0 +1,58 @@
+//! Lifecycle of a connection registered with the manager.
+
+mod support;
+
+use std::time::Duration;
+
+use dev_null_backend::ws::management::{Channel, Command, CommandResponse, Manager};
+use tokio::sync::mpsc;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn idle_manager() -> Manager {
+    Manager::with_config(support::idle_config())
+}
+
+#[tokio::test]
+async fn unregister_ends_subscriptions() {
+    let manager = idle_manager();
+    let (tx, mut rx) = mpsc::channel(16);
+    let Some(CommandResponse::Registered(id)) = manager.send_command(Command::Register(tx)).await
+    else {
+        panic!("websocket should be registered");
+    };
+
+    for channel in Channel::ALL {
+        assert!(matches!(
+            manager.send_command(Command::Subscribe(id, *channel)).await,
+            Some(CommandResponse::Subscribed)
+        ));
+    }
+    for _ in Channel::ALL {
+        tokio::time::timeout(TIMEOUT, rx.recv())
+            .await
+            .expect("snapshot should arrive in time")
+            .expect("subscription should be open");
+    }
+
+    assert!(matches!(
+        manager.send_command(Command::Unregister(id)).await,
+        Some(CommandResponse::Unregistered)
+    ));
+    let closed = tokio::time::timeout(TIMEOUT, rx.recv())
+        .await
+        .expect("subscriptions should end in time");
+    assert!(closed.is_none());
+
+    for command in [
+        Command::Subscribe(id, Channel::Minecraft),
+        Command::Unsubscribe(id, Channel::Minecraft),
+        Command::Authenticate(id, "token".to_owned()),
+        Command::Unregister(id),
+    ] {
+        assert!(matches!(
+            manager.send_command(command).await,
+            Some(CommandResponse::UnknownId)
+        ));
+    }
+}
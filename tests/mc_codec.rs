@@ -0,0 +1,146 @@
+//! Property tests for the Minecraft packet codec.
+
+use std::collections::BTreeMap;
+
+use bytes::BufMut;
+use dev_null_backend::report::mc::{
+    bytes::Bytes,
+    packet::{status::decode_forge_data_d, Packet},
+};
+use proptest::{collection::vec, option, prelude::*};
+
+/// Inverse of the decoder Forge uses for `forgeData.d`: two chars of length
+/// followed by the data packed into 15 bits per char.
+fn encode_optimized(data: &[u8]) -> String {
+    let mut chars = vec![
+        (data.len() & 0x7FFF) as u16,
+        ((data.len() >> 15) & 0x7FFF) as u16,
+    ];
+
+    let mut buffer: u32 = 0;
+    let mut bits_in_buffer = 0;
+    for &byte in data {
+        buffer |= (byte as u32) << bits_in_buffer;
+        bits_in_buffer += 8;
+        while bits_in_buffer >= 15 {
+            chars.push((buffer & 0x7FFF) as u16);
+            buffer >>= 15;
+            bits_in_buffer -= 15;
+        }
+    }
+    if bits_in_buffer > 0 {
+        chars.push((buffer & 0x7FFF) as u16);
+    }
+
+    String::from_utf16(&chars).expect("15 bit chars are never surrogates")
+}
+
+#[derive(Debug, Clone)]
+struct ForgeMod {
+    version: Option<String>,
+    channels: Vec<(String, String, bool)>,
+}
+
+fn forge_mod() -> impl Strategy<Value = ForgeMod> {
+    (
+        option::of("[0-9.]{1,10}"),
+        vec(("[a-z_]{1,12}", "[0-9.]{0,6}", any::<bool>()), 0..3),
+    )
+        .prop_map(|(version, channels)| ForgeMod { version, channels })
+}
+
+fn encode_forge_data_d(truncated: bool, mods: &BTreeMap<String, ForgeMod>) -> String {
+    let mut d: Bytes = vec![].into();
+    d.put_u8(truncated.into());
+    d.put_u16(mods.len() as u16);
+    for (mod_id, forge_mod) in mods {
+        let flags = (forge_mod.channels.len() << 1) | usize::from(forge_mod.version.is_none());
+        d.put_varint(flags as i32);
+        d.put_string(mod_id);
+        if let Some(version) = &forge_mod.version {
+            d.put_string(version);
+        }
+        for (name, version, required) in &forge_mod.channels {
+            d.put_string(name);
+            d.put_string(version);
+            d.put_u8((*required).into());
+        }
+    }
+    d.put_varint(1);
+    d.put_string("minecraft:register");
+    d.put_string("FML3");
+    d.put_u8(0);
+
+    let data: Vec<u8> = d.into();
+    encode_optimized(&data)
+}
+
+proptest! {
+    #[test]
+    fn packet_round_trip(id in any::<i32>(), data in vec(any::<u8>(), 0..512)) {
+        let frame: Vec<u8> = Packet { id, data: data.clone().into() }.into();
+
+        let packet = Packet::recv(&mut &frame[..]).unwrap();
+        prop_assert_eq!(packet.id, id);
+        prop_assert_eq!(Vec::<u8>::from(packet.data), data);
+    }
+
+    #[test]
+    fn packet_recv_never_panics(frame in vec(any::<u8>(), 0..64)) {
+        let _ = Packet::recv(&mut &frame[..]);
+    }
+
+    #[test]
+    fn string_round_trip(string in any::<String>()) {
+        let mut bytes: Bytes = vec![].into();
+        bytes.put_string(&string);
+        prop_assert_eq!(bytes.get_string().unwrap(), string);
+        prop_assert!(bytes.is_empty());
+    }
+
+    #[test]
+    fn truncated_string_is_an_error(string in ".{1,32}", cut in 1usize..8) {
+        let mut bytes: Bytes = vec![].into();
+        bytes.put_string(&string);
+        let mut data: Vec<u8> = bytes.into();
+        data.truncate(data.len().saturating_sub(cut));
+
+        let mut bytes: Bytes = data.into();
+        prop_assert!(bytes.get_string().is_err());
+    }
+
+    #[test]
+    fn varint_round_trip(value in any::<i32>()) {
+        let mut bytes: Bytes = vec![].into();
+        bytes.put_varint(value);
+        prop_assert_eq!(bytes.get_varint().unwrap(), value);
+        prop_assert!(bytes.is_empty());
+    }
+
+    #[test]
+    fn forge_data_d_round_trip(
+        truncated in any::<bool>(),
+        mods in proptest::collection::btree_map("[a-z_]{1,16}", forge_mod(), 0..8),
+    ) {
+        let d = decode_forge_data_d(encode_forge_data_d(truncated, &mods)).unwrap();
+
+        prop_assert_eq!(d.truncated, truncated);
+        prop_assert_eq!(d.mods_size, mods.len());
+        for (mod_id, forge_mod) in &mods {
+            let decoded = d.get_mod(mod_id).unwrap();
+            prop_assert_eq!(&decoded.version, &forge_mod.version);
+            prop_assert_eq!(decoded.ignore_server_only, forge_mod.version.is_none());
+            prop_assert_eq!(decoded.channels.len(), forge_mod.channels.len());
+            for (channel, (name, version, required)) in decoded.channels.iter().zip(&forge_mod.channels) {
+                prop_assert_eq!(&channel.name, &format!("{mod_id}:{name}"));
+                prop_assert_eq!(&channel.version, version);
+                prop_assert_eq!(channel.required_on_client, *required);
+            }
+        }
+    }
+
+    #[test]
+    fn forge_data_d_never_panics(d in any::<String>()) {
+        let _ = decode_forge_data_d(d);
+    }
+}
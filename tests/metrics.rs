@@ -0,0 +1,37 @@
+//! Checks requests are counted per matched route rather than per path.
+
+use axum::{extract::Path, middleware, routing::get, Router};
+use dev_null_backend::metrics::{self, METRICS};
+use hyper::Client;
+
+#[tokio::test]
+async fn requests_per_route() {
+    let app = Router::new()
+        .route(
+            "/players/:player",
+            get(|Path(player): Path<String>| async { player }),
+        )
+        .route_layer(middleware::from_fn(metrics::track_requests));
+    let server = axum::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(app.into_make_service());
+    let addr = server.local_addr();
+    tokio::spawn(server);
+
+    let client = Client::new();
+    for player in ["alice", "bob"] {
+        let uri = format!("http://{addr}/players/{player}").parse().unwrap();
+        let response = client.get(uri).await.unwrap();
+        hyper::body::to_bytes(response.into_body()).await.unwrap();
+    }
+    let uri = format!("http://{addr}/unknown").parse().unwrap();
+    client.get(uri).await.unwrap();
+
+    let text = METRICS.render();
+    assert!(text.contains(
+        "http_requests_total{method=\"GET\",route=\"/players/:player\",status=\"200\"} 2\n"
+    ));
+    assert!(text.contains(
+        "http_request_duration_seconds_count{method=\"GET\",route=\"/players/:player\"} 2\n"
+    ));
+    // unmatched paths are left out instead of making a series each
+    assert!(!text.contains("/unknown"));
+}
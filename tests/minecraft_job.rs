@@ -0,0 +1,145 @@
+//! Drives `MinecraftJob` against a fake server and checks the exact updates a
+//! subscribed WebSocket receives.
+
+mod support;
+
+use std::time::Duration;
+
+use dev_null_backend::ws::{
+    management::{Channel, Command, CommandResponse, Manager, SubscriptionResponse},
+    minecraft::MinecraftJobConfig,
+};
+use serde_json::Value;
+use support::fake_server::{FakeServer, Reply, Script};
+use tokio::sync::mpsc;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+async fn subscribe(config: MinecraftJobConfig) -> mpsc::Receiver<SubscriptionResponse> {
+    let manager = Manager::with_minecraft_config(config);
+    let (tx, rx) = mpsc::channel(16);
+
+    let Some(CommandResponse::Registered(id)) = manager.send_command(Command::Register(tx)).await
+    else {
+        panic!("websocket should be registered");
+    };
+    manager
+        .send_command(Command::Subscribe(id, Channel::Minecraft))
+        .await;
+
+    rx
+}
+
+async fn next_messages(rx: &mut mpsc::Receiver<SubscriptionResponse>, count: usize) -> Vec<Value> {
+    let mut messages = vec![];
+    for _ in 0..count {
+        let response = tokio::time::timeout(TIMEOUT, rx.recv())
+            .await
+            .expect("update should arrive in time")
+            .expect("subscription should stay open");
+        messages.push(serde_json::to_value(response).unwrap()["message"].clone());
+    }
+    messages
+}
+
+async fn assert_no_messages(rx: &mut mpsc::Receiver<SubscriptionResponse>) {
+    if let Ok(Some(response)) = tokio::time::timeout(Duration::from_millis(200), rx.recv()).await {
+        panic!(
+            "unexpected update {}",
+            serde_json::to_value(response).unwrap()
+        );
+    }
+}
+
+fn types(messages: &[Value]) -> Vec<&str> {
+    messages
+        .iter()
+        .map(|message| message["type"].as_str().unwrap())
+        .collect()
+}
+
+fn assert_favicon(message: &Value) {
+    let hash = message["favicon"]["hash"].as_str().unwrap();
+    let url = message["favicon"]["url"].as_str().unwrap();
+    assert!(url.ends_with(hash));
+}
+
+#[tokio::test]
+async fn status_updates() {
+    let server = FakeServer::start(Script {
+        status_reply: Reply::Drop,
+        ..Script::new(include_str!("fixtures/vanilla_1_20_1.json"))
+    })
+    .await;
+    let mut rx = subscribe(MinecraftJobConfig {
+        server_addr: Some(server.addr()),
+        ping_interval: None,
+        status_interval: Some(Duration::from_millis(20)),
+        ..Default::default()
+    })
+    .await;
+
+    let initial = next_messages(&mut rx, 1).await;
+    assert_eq!(types(&initial), ["initial"]);
+    assert_eq!(initial[0]["online"], false);
+
+    server.set_status_reply(Reply::Respond);
+    // the players of the first sample were already online, not joining
+    let messages = next_messages(&mut rx, 4).await;
+    assert_eq!(
+        types(&messages),
+        ["status", "onlinePlayers", "version", "favicon"]
+    );
+    assert_eq!(messages[0]["online"], true);
+    assert_eq!(messages[1]["playersOnline"], 2);
+    assert_eq!(messages[2]["protocol"], 763);
+    assert_favicon(&messages[3]);
+    assert_no_messages(&mut rx).await;
+
+    let status = include_str!("fixtures/vanilla_1_20_1.json").replace("jeb_", "Dinnerbone");
+    server.set_status(&status);
+    let messages = next_messages(&mut rx, 2).await;
+    assert_eq!(types(&messages), ["playerLeft", "playerJoined"]);
+    assert_eq!(messages[0]["name"], "jeb_");
+    assert_eq!(messages[1]["name"], "Dinnerbone");
+
+    server.set_status_reply(Reply::Malformed(vec![0xFF; 6]));
+    let messages = next_messages(&mut rx, 1).await;
+    assert_eq!(types(&messages), ["status"]);
+    assert_eq!(messages[0]["online"], false);
+}
+
+#[tokio::test]
+async fn ping_updates() {
+    let server = FakeServer::start(Script {
+        ping_reply: Reply::Drop,
+        ..Script::new(include_str!("fixtures/vanilla_1_20_1.json"))
+    })
+    .await;
+    let mut rx = subscribe(MinecraftJobConfig {
+        server_addr: Some(server.addr()),
+        ping_interval: Some(Duration::from_millis(20)),
+        status_interval: None,
+        ..Default::default()
+    })
+    .await;
+
+    let initial = next_messages(&mut rx, 1).await;
+    assert_eq!(types(&initial), ["initial"]);
+
+    server.set_ping_reply(Reply::Respond);
+    let messages = next_messages(&mut rx, 2).await;
+    assert_eq!(types(&messages), ["status", "latency"]);
+    assert_eq!(messages[0]["online"], true);
+    assert!(messages[1]["latencyMs"].is_f64());
+
+    server.set_ping_reply(Reply::Delay(Duration::from_millis(150)));
+    let messages = next_messages(&mut rx, 1).await;
+    assert_eq!(types(&messages), ["latency"]);
+    assert!(messages[0]["latencyMs"].as_f64().unwrap() >= 150.0);
+
+    server.set_ping_reply(Reply::Drop);
+    let messages = next_messages(&mut rx, 1).await;
+    assert_eq!(types(&messages), ["status"]);
+    assert_eq!(messages[0]["online"], false);
+}
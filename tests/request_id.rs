@@ -0,0 +1,44 @@
+//! Checks every request gets an id, and keeps the one a proxy already set.
+
+use axum::{middleware, routing::get, Extension, Router};
+use dev_null_backend::logging::request::{assign_request_id, request_span, RequestId};
+use hyper::{Body, Client, Request};
+use tower_http::trace::TraceLayer;
+
+#[tokio::test]
+async fn request_ids() {
+    let app = Router::new()
+        .route(
+            "/",
+            get(|Extension(id): Extension<RequestId>| async { id.0 }),
+        )
+        .layer(TraceLayer::new_for_http().make_span_with(request_span))
+        .layer(middleware::from_fn(assign_request_id));
+    let server = axum::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(app.into_make_service());
+    let addr = server.local_addr();
+    tokio::spawn(server);
+
+    let client = Client::new();
+    let mut ids = vec![];
+    for _ in 0..2 {
+        let response = client
+            .get(format!("http://{addr}/").parse().unwrap())
+            .await
+            .unwrap();
+        let header = response.headers()["x-request-id"]
+            .to_str()
+            .unwrap()
+            .to_owned();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(header.as_bytes(), body);
+        ids.push(header);
+    }
+    assert_ne!(ids[0], ids[1]);
+
+    let request = Request::get(format!("http://{addr}/"))
+        .header("x-request-id", "from-proxy")
+        .body(Body::empty())
+        .unwrap();
+    let response = client.request(request).await.unwrap();
+    assert_eq!(response.headers()["x-request-id"], "from-proxy");
+}
@@ -0,0 +1,154 @@
+//! Follows the `/events` stream against a fake server, including resuming it
+//! with `Last-Event-ID`.
+
+mod support;
+
+use std::{
+    collections::{HashMap, HashSet},
+    net::SocketAddr,
+    time::Duration,
+};
+
+use axum::{routing::get, Router};
+use dev_null_backend::ws::{
+    auth::AuthConfig,
+    management::{Manager, ManagerConfig},
+    minecraft::MinecraftJobConfig,
+    sse::sse_handler,
+};
+use hyper::{body::HttpBody, Body, Client, Request, StatusCode};
+use serde_json::Value;
+use support::fake_server::{FakeServer, Reply, Script};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn serve(config: ManagerConfig) -> SocketAddr {
+    let app = Router::new()
+        .route("/events", get(sse_handler))
+        .with_state(Manager::with_config(config));
+    let server = axum::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(app.into_make_service());
+    let addr = server.local_addr();
+    tokio::spawn(server);
+    addr
+}
+
+struct Events {
+    body: Body,
+    buffer: String,
+}
+
+impl Events {
+    async fn open(addr: SocketAddr, last_event_id: Option<u64>) -> Self {
+        let mut request = Request::get(format!("http://{addr}/events?channel=minecraft"));
+        if let Some(id) = last_event_id {
+            request = request.header("Last-Event-ID", id.to_string());
+        }
+        let response = Client::new()
+            .request(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        Self {
+            body: response.into_body(),
+            buffer: String::new(),
+        }
+    }
+
+    /// The next event, as its id and data.
+    async fn next(&mut self) -> (u64, Value) {
+        loop {
+            if let Some(end) = self.buffer.find("\n\n") {
+                let event: String = self.buffer.drain(..end + 2).collect();
+                let fields: HashMap<_, _> = event
+                    .lines()
+                    .filter_map(|line| line.split_once(':'))
+                    .collect();
+                if let (Some(id), Some(data)) = (fields.get("id"), fields.get("data")) {
+                    return (id.parse().unwrap(), serde_json::from_str(data).unwrap());
+                }
+                continue;
+            }
+            let chunk = tokio::time::timeout(TIMEOUT, self.body.data())
+                .await
+                .expect("event should arrive in time")
+                .expect("stream should stay open")
+                .unwrap();
+            self.buffer.push_str(std::str::from_utf8(&chunk).unwrap());
+        }
+    }
+}
+
+#[tokio::test]
+async fn resume_with_last_event_id() {
+    let server = FakeServer::start(Script {
+        status_reply: Reply::Drop,
+        ..Script::new(include_str!("fixtures/vanilla_1_20_1.json"))
+    })
+    .await;
+    let addr = serve(ManagerConfig {
+        minecraft: MinecraftJobConfig {
+            server_addr: Some(server.addr()),
+            ping_interval: None,
+            status_interval: Some(Duration::from_millis(20)),
+            ..Default::default()
+        },
+        ..support::idle_config()
+    });
+
+    let mut events = Events::open(addr, None).await;
+    let (snapshot_id, initial) = events.next().await;
+    assert_eq!(initial["channel"], "minecraft");
+    assert_eq!(initial["message"]["type"], "initial");
+
+    server.set_status_reply(Reply::Respond);
+    let mut types = vec![];
+    for offset in 1..=4 {
+        let (id, message) = events.next().await;
+        assert_eq!(id, snapshot_id + offset);
+        types.push(message["message"]["type"].as_str().unwrap().to_owned());
+    }
+    assert_eq!(types, ["status", "onlinePlayers", "version", "favicon"]);
+    drop(events);
+
+    let mut events = Events::open(addr, Some(snapshot_id + 1)).await;
+    let (id, message) = events.next().await;
+    assert_eq!(id, snapshot_id + 2);
+    assert_eq!(message["message"]["type"], "onlinePlayers");
+
+    // an id from before a restart gets a fresh snapshot
+    let mut events = Events::open(addr, Some(u64::MAX)).await;
+    let (id, message) = events.next().await;
+    assert_eq!(id, snapshot_id + 4);
+    assert_eq!(message["message"]["type"], "initial");
+}
+
+#[tokio::test]
+async fn forbidden_channel() {
+    let addr = serve(ManagerConfig {
+        auth: AuthConfig {
+            public_channels: HashSet::new(),
+            tokens: HashMap::new(),
+        },
+        ..support::idle_config()
+    });
+
+    let response = Client::new()
+        .get(
+            format!("http://{addr}/events?channel=minecraft")
+                .parse()
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let response = Client::new()
+        .get(
+            format!("http://{addr}/events?channel=minecraft&token=guess")
+                .parse()
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
@@ -0,0 +1,119 @@
+//! Status responses modeled on what vanilla, Paper, Fabric and Forge servers
+//! send, pushed through the packet framing the same way a live response is.
+//! The fixtures are hand-written until replaced by real captures, see
+//! `fixtures/README.md`.
+
+use dev_null_backend::report::mc::{
+    bytes::Bytes,
+    packet::{
+        status::{ServerType, StatusResponse},
+        Packet,
+    },
+};
+
+fn parse_fixture(json: &str) -> StatusResponse {
+    let mut data: Bytes = vec![].into();
+    data.put_string(json);
+    let frame: Vec<u8> = Packet { id: 0x00, data }.into();
+
+    let packet = Packet::recv(&mut &frame[..]).expect("status packet should be framed");
+    packet.try_into().expect("status response should parse")
+}
+
+#[test]
+fn vanilla_1_20_1() {
+    let status = parse_fixture(include_str!("fixtures/vanilla_1_20_1.json"));
+
+    assert_eq!(status.server_type, ServerType::Vanilla);
+    assert_eq!(status.description.text, "A Minecraft Server");
+    assert_eq!(status.version.protocol, 763);
+    assert!(status.enforces_secure_chat);
+    assert!(status.forge_data.is_none());
+
+    assert_eq!(status.players.online, 2);
+    let names: Vec<_> = status.players.sample.iter().map(|p| &p.name[..]).collect();
+    assert_eq!(names, ["Notch", "jeb_"]);
+
+    let favicon = status.favicon.expect("favicon should be decoded");
+    assert!(favicon.png.starts_with(b"\x89PNG"));
+    assert!(favicon.url.ends_with(&favicon.hash));
+}
+
+#[test]
+fn vanilla_1_8_9_plain_description() {
+    let status = parse_fixture(include_str!("fixtures/vanilla_1_8_9.json"));
+
+    assert_eq!(status.server_type, ServerType::Vanilla);
+    assert_eq!(status.description.text, "A Minecraft Server");
+    assert!(status.favicon.is_none());
+    assert!(status.players.sample.is_empty());
+}
+
+#[test]
+fn paper_1_20_4() {
+    let status = parse_fixture(include_str!("fixtures/paper_1_20_4.json"));
+
+    assert_eq!(status.server_type, ServerType::Paper);
+    assert_eq!(status.players.max, 50);
+}
+
+#[test]
+fn fabric_1_20_1() {
+    let status = parse_fixture(include_str!("fixtures/fabric_1_20_1.json"));
+
+    // Fabric doesn't announce itself in the status response
+    assert_eq!(status.server_type, ServerType::Vanilla);
+    assert!(status.prevents_chat_reports);
+    assert_eq!(status.players.sample.len(), 1);
+}
+
+#[test]
+fn forge_1_12_2_modinfo() {
+    let status = parse_fixture(include_str!("fixtures/forge_1_12_2.json"));
+
+    assert_eq!(status.server_type, ServerType::Forge);
+    let forge_data = status.forge_data.expect("modinfo should become forge data");
+    assert_eq!(forge_data.fml_network_version, 1);
+    assert_eq!(forge_data.d.mods_size, 5);
+    let forge = forge_data.d.get_mod("forge").unwrap();
+    assert_eq!(forge.version.as_deref(), Some("14.23.5.2860"));
+    assert!(forge.required_on_client);
+    assert!(!forge.ignore_server_only);
+}
+
+#[test]
+fn forge_1_16_5_fml2() {
+    let status = parse_fixture(include_str!("fixtures/forge_1_16_5.json"));
+
+    assert_eq!(status.server_type, ServerType::Forge);
+    let forge_data = status.forge_data.expect("forge data should be parsed");
+    assert_eq!(forge_data.fml_network_version, 2);
+    let jei = forge_data.d.get_mod("jei").unwrap();
+    assert_eq!(jei.version.as_deref(), Some("7.7.1.153"));
+    assert_eq!(jei.channels.len(), 1);
+    assert_eq!(jei.channels[0].name, "jei:channel");
+    assert!(jei.channels[0].required_on_client);
+    assert_eq!(forge_data.d.get_mod("forge").unwrap().channels.len(), 1);
+    assert!(forge_data.d.channels.is_empty());
+}
+
+#[test]
+fn forge_1_20_1_fml3() {
+    let status = parse_fixture(include_str!("fixtures/forge_1_20_1.json"));
+
+    assert_eq!(status.server_type, ServerType::Forge);
+    let forge_data = status.forge_data.expect("forge data should be decoded");
+    assert_eq!(forge_data.fml_network_version, 3);
+    assert!(!forge_data.d.truncated);
+    assert_eq!(forge_data.d.mods_size, 4);
+    let version = |id| forge_data.d.get_mod(id).unwrap().version.as_deref();
+    assert_eq!(version("forge"), Some("47.2.0"));
+    assert_eq!(version("jei"), Some("15.2.0.27"));
+    let spark = forge_data.d.get_mod("spark").unwrap();
+    assert_eq!(spark.version, None);
+    assert!(spark.ignore_server_only);
+    assert!(!spark.required_on_client);
+    let mod_channels: usize = forge_data.d.mods.iter().map(|m| m.channels.len()).sum();
+    assert_eq!(mod_channels, 3);
+    assert_eq!(forge_data.d.channels.len(), 2);
+}
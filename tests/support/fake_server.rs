@@ -0,0 +1,163 @@
+//! Minimal Minecraft server speaking the server side of handshake, status and
+//! ping, with replies that can be changed while it runs.
+
+use std::{
+    io,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use dev_null_backend::report::mc::{bytes::Bytes, packet::Packet, varint::VarInt};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    task::JoinHandle,
+};
+
+/// How the server answers a status request or a ping.
+#[derive(Debug, Clone)]
+pub enum Reply {
+    Respond,
+    Delay(Duration),
+    /// Close the connection without answering.
+    Drop,
+    /// Send these bytes instead of the answer and close the connection.
+    Malformed(Vec<u8>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Script {
+    pub status: String,
+    pub status_reply: Reply,
+    pub ping_reply: Reply,
+}
+
+impl Script {
+    pub fn new(status: &str) -> Self {
+        Self {
+            status: status.to_owned(),
+            status_reply: Reply::Respond,
+            ping_reply: Reply::Respond,
+        }
+    }
+}
+
+pub struct FakeServer {
+    addr: SocketAddr,
+    script: Arc<Mutex<Script>>,
+    task: JoinHandle<()>,
+}
+
+impl FakeServer {
+    pub async fn start(script: Script) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let script = Arc::new(Mutex::new(script));
+
+        let connection_script = script.clone();
+        let task = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(handle_connection(stream, connection_script.clone()));
+            }
+        });
+
+        Self { addr, script, task }
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn set_status(&self, status: &str) {
+        self.script.lock().unwrap().status = status.to_owned();
+    }
+
+    pub fn set_status_reply(&self, reply: Reply) {
+        self.script.lock().unwrap().status_reply = reply;
+    }
+
+    pub fn set_ping_reply(&self, reply: Reply) {
+        self.script.lock().unwrap().ping_reply = reply;
+    }
+}
+
+impl Drop for FakeServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn handle_connection(mut stream: TcpStream, script: Arc<Mutex<Script>>) {
+    while let Ok(Some(mut data)) = read_frame(&mut stream).await {
+        let Ok(id) = data.get_varint() else {
+            return;
+        };
+        let script = script.lock().unwrap().clone();
+
+        let (reply, response) = match id {
+            // handshake, the status request is the same id without any data
+            0x00 if !data.is_empty() => continue,
+            0x00 => {
+                let mut status: Bytes = vec![].into();
+                status.put_string(&script.status);
+                (
+                    script.status_reply,
+                    Packet {
+                        id: 0x00,
+                        data: status,
+                    },
+                )
+            }
+            0x01 => (script.ping_reply, Packet { id: 0x01, data }),
+            _ => return,
+        };
+
+        let keep_open = match reply {
+            Reply::Respond => write_packet(&mut stream, response).await.is_ok(),
+            Reply::Delay(delay) => {
+                tokio::time::sleep(delay).await;
+                write_packet(&mut stream, response).await.is_ok()
+            }
+            Reply::Drop => false,
+            Reply::Malformed(bytes) => {
+                let _ = stream.write_all(&bytes).await;
+                false
+            }
+        };
+        if !keep_open {
+            return;
+        }
+    }
+}
+
+/// Reads a single length prefixed frame, `None` when the connection is closed
+/// or the client sent a legacy `0xFE` ping, which isn't supported.
+async fn read_frame(stream: &mut TcpStream) -> io::Result<Option<Bytes>> {
+    let mut length_bytes = vec![];
+    loop {
+        let Ok(byte) = stream.read_u8().await else {
+            return Ok(None);
+        };
+        if length_bytes.is_empty() && byte == 0xFE {
+            return Ok(None);
+        }
+        length_bytes.push(byte);
+        if byte & 0x80 == 0 || length_bytes.len() == VarInt::MAX_SIZE {
+            break;
+        }
+    }
+
+    let mut length_bytes: Bytes = length_bytes.into();
+    let VarInt(length) = VarInt::decode(|| length_bytes.get_u8()).map_err(io::Error::other)?;
+    let length = usize::try_from(length).map_err(io::Error::other)?;
+    let mut frame = vec![0u8; length];
+    stream.read_exact(&mut frame).await?;
+
+    Ok(Some(frame.into()))
+}
+
+async fn write_packet(stream: &mut TcpStream, packet: Packet) -> io::Result<()> {
+    let frame: Vec<u8> = packet.into();
+    stream.write_all(&frame).await
+}
@@ -0,0 +1,25 @@
+// each test crate only uses part of the helpers
+#![allow(dead_code)]
+
+pub mod fake_server;
+
+use dev_null_backend::ws::{
+    bedrock::BedrockJobConfig, management::ManagerConfig, minecraft::MinecraftJobConfig,
+};
+
+/// Jobs that never poll, for tests about the connections rather than the
+/// updates.
+pub fn idle_config() -> ManagerConfig {
+    ManagerConfig {
+        minecraft: MinecraftJobConfig {
+            ping_interval: None,
+            status_interval: None,
+            ..Default::default()
+        },
+        bedrock: BedrockJobConfig {
+            status_interval: None,
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
@@ -0,0 +1,133 @@
+//! Checks that the manager only lets connections subscribe to the channels
+//! their token allows.
+
+mod support;
+
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+use dev_null_backend::ws::{
+    auth::AuthConfig,
+    management::{Channel, Command, CommandResponse, Manager, ManagerConfig, UniqueId},
+    minecraft::MinecraftJobConfig,
+};
+use support::fake_server::{FakeServer, Reply, Script};
+use tokio::sync::mpsc;
+
+async fn register(manager: &Manager) -> UniqueId {
+    let (tx, _rx) = mpsc::channel(16);
+    let Some(CommandResponse::Registered(id)) = manager.send_command(Command::Register(tx)).await
+    else {
+        panic!("websocket should be registered");
+    };
+    id
+}
+
+#[tokio::test]
+async fn channel_permissions() {
+    let manager = Manager::with_config(ManagerConfig {
+        auth: AuthConfig {
+            public_channels: HashSet::new(),
+            tokens: HashMap::from([("secret".to_owned(), HashSet::from([Channel::Bedrock]))]),
+        },
+        ..support::idle_config()
+    });
+    let id = register(&manager).await;
+
+    assert!(matches!(
+        manager
+            .send_command(Command::Subscribe(id, Channel::Bedrock))
+            .await,
+        Some(CommandResponse::Forbidden)
+    ));
+    assert!(matches!(
+        manager
+            .send_command(Command::Authenticate(id, "guess".to_owned()))
+            .await,
+        Some(CommandResponse::Unauthorized)
+    ));
+
+    let Some(CommandResponse::Authenticated(channels)) = manager
+        .send_command(Command::Authenticate(id, "secret".to_owned()))
+        .await
+    else {
+        panic!("token should be accepted");
+    };
+    assert_eq!(channels, vec![Channel::Bedrock]);
+
+    assert!(matches!(
+        manager
+            .send_command(Command::Subscribe(id, Channel::Minecraft))
+            .await,
+        Some(CommandResponse::Forbidden)
+    ));
+    assert!(matches!(
+        manager
+            .send_command(Command::Subscribe(id, Channel::Bedrock))
+            .await,
+        Some(CommandResponse::Subscribed)
+    ));
+}
+
+#[tokio::test]
+async fn weaker_token_revokes_subscriptions() {
+    let server = FakeServer::start(Script {
+        status_reply: Reply::Drop,
+        ..Script::new(include_str!("fixtures/vanilla_1_20_1.json"))
+    })
+    .await;
+    let manager = Manager::with_config(ManagerConfig {
+        minecraft: MinecraftJobConfig {
+            server_addr: Some(server.addr()),
+            ping_interval: None,
+            status_interval: Some(Duration::from_millis(20)),
+            ..Default::default()
+        },
+        auth: AuthConfig {
+            public_channels: HashSet::new(),
+            tokens: HashMap::from([
+                ("admin".to_owned(), Channel::ALL.iter().copied().collect()),
+                ("guest".to_owned(), HashSet::from([Channel::Bedrock])),
+            ]),
+        },
+        ..support::idle_config()
+    });
+    let (tx, mut rx) = mpsc::channel(16);
+    let Some(CommandResponse::Registered(id)) = manager.send_command(Command::Register(tx)).await
+    else {
+        panic!("websocket should be registered");
+    };
+
+    manager
+        .send_command(Command::Authenticate(id, "admin".to_owned()))
+        .await;
+    assert!(matches!(
+        manager
+            .send_command(Command::Subscribe(id, Channel::Minecraft))
+            .await,
+        Some(CommandResponse::Subscribed)
+    ));
+    tokio::time::timeout(Duration::from_secs(5), rx.recv())
+        .await
+        .expect("snapshot should arrive in time");
+
+    let Some(CommandResponse::Authenticated(channels)) = manager
+        .send_command(Command::Authenticate(id, "guest".to_owned()))
+        .await
+    else {
+        panic!("token should be accepted");
+    };
+    assert_eq!(channels, vec![Channel::Bedrock]);
+    assert!(matches!(
+        manager.send_command(Command::Channels(id)).await,
+        Some(CommandResponse::Channels(channels)) if channels == vec![Channel::Bedrock]
+    ));
+
+    // the server coming online would be an update, were the subscription left
+    server.set_status_reply(Reply::Respond);
+    if let Ok(Some(response)) = tokio::time::timeout(Duration::from_millis(300), rx.recv()).await {
+        panic!("update after revoking the channel: {response:?}");
+    }
+}
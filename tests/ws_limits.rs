@@ -0,0 +1,112 @@
+//! Drives `/ws` with a real client to check the connections limits are
+//! enforced with the right close codes.
+
+mod support;
+
+use std::{net::SocketAddr, time::Duration};
+
+use axum::{routing::get, Router};
+use dev_null_backend::ws::{
+    handler::{ws_handler, WsState},
+    limits::WsLimits,
+    management::Manager,
+};
+use futures_util::{SinkExt, StreamExt};
+use tokio::net::TcpStream;
+use tokio_tungstenite::{
+    tungstenite::{protocol::frame::coding::CloseCode, Message},
+    MaybeTlsStream, WebSocketStream,
+};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+type Client = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+fn serve(limits: WsLimits) -> SocketAddr {
+    let manager = Manager::with_config(support::idle_config());
+    let app = Router::new()
+        .route("/ws", get(ws_handler))
+        .with_state(WsState::new(manager, limits));
+    let server = axum::Server::bind(&"127.0.0.1:0".parse().unwrap())
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>());
+    let addr = server.local_addr();
+    tokio::spawn(server);
+    addr
+}
+
+async fn next_message(client: &mut Client) -> Option<Message> {
+    tokio::time::timeout(TIMEOUT, client.next())
+        .await
+        .expect("message should arrive in time")
+        .map(|message| message.unwrap())
+}
+
+/// Connects and answers the first ping, up to the `hello` frame.
+async fn connect(addr: SocketAddr) -> Client {
+    let (mut client, _) = tokio_tungstenite::connect_async(format!("ws://{addr}/ws"))
+        .await
+        .unwrap();
+    loop {
+        match next_message(&mut client).await {
+            Some(Message::Text(text)) if text.contains("hello") => return client,
+            Some(Message::Ping(_)) => client.send(Message::Pong(vec![])).await.unwrap(),
+            message => panic!("unexpected message {message:?}"),
+        }
+    }
+}
+
+async fn close_code(client: &mut Client) -> CloseCode {
+    loop {
+        match next_message(client).await {
+            Some(Message::Close(Some(frame))) => return frame.code,
+            Some(_) => continue,
+            None => panic!("connection closed without a close frame"),
+        }
+    }
+}
+
+#[tokio::test]
+async fn idle_timeout() {
+    let addr = serve(WsLimits {
+        ping_interval: Duration::from_millis(50),
+        idle_timeout: Duration::from_millis(200),
+        ..Default::default()
+    });
+
+    let mut client = connect(addr).await;
+    // a silent client is never answering pings
+    tokio::time::sleep(Duration::from_millis(400)).await;
+    assert_eq!(close_code(&mut client).await, CloseCode::Away);
+}
+
+#[tokio::test]
+async fn connections_per_ip() {
+    let addr = serve(WsLimits {
+        max_connections_per_ip: 1,
+        ..Default::default()
+    });
+
+    let _first = connect(addr).await;
+    let (mut second, _) = tokio_tungstenite::connect_async(format!("ws://{addr}/ws"))
+        .await
+        .unwrap();
+    assert_eq!(close_code(&mut second).await, CloseCode::Again);
+}
+
+#[tokio::test]
+async fn message_rate() {
+    let addr = serve(WsLimits {
+        max_messages_per_second: 2,
+        ..Default::default()
+    });
+
+    let mut client = connect(addr).await;
+    for _ in 0..3 {
+        let command = r#"{"command": "subscribe", "channel": "minecraft"}"#;
+        client
+            .send(Message::Text(command.to_owned()))
+            .await
+            .unwrap();
+    }
+    assert_eq!(close_code(&mut client).await, CloseCode::Policy);
+}